@@ -1,6 +1,14 @@
 fn main() {
     #[cfg(feature = "graphics")]
     {
-        res::generate(&[res::shader("gui").transform().graphic()]);
+        res::generate(&[
+            res::shader("gui").transform().graphic(),
+            res::shader("text").transform().graphic().tint().blend(),
+            res::shader("sprite")
+                .transform()
+                .gradient()
+                .graphic()
+                .blend(),
+        ]);
     }
 }
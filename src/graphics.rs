@@ -33,6 +33,11 @@
 //! When putting a shape into a group you may attach a transform and optionally
 //! texture coordinates to it.
 //!
+//! ## Headless
+//! Graphics can also be rendered on the CPU into a `Raster` with [`Headless`]
+//! instead of opening a window with [`draw_thread()`].  This is useful for
+//! testing on machines without a GPU.
+//!
 //! # Coordinate System
 //! ![X goes from 0 to 1, Y goes from 0 to `Canvas.height()`](https://raw.githubusercontent.com/libcala/window/5205e59f0cd9f37a619f590e94218900afc2395b/res/coordinate_system.svg)
 //!
@@ -124,14 +129,66 @@
 
 use std::{
     cell::RefCell,
-    mem::MaybeUninit,
+    collections::{hash_map::Entry, HashMap},
     sync::{
         atomic::{AtomicU32, Ordering},
-        Arc, Condvar, Mutex, Once,
+        Arc, Condvar, Mutex, OnceLock,
     },
     task::Waker,
+    time::Instant,
 };
 
+mod animation;
+mod atlas;
+mod camera;
+#[doc(hidden)]
+pub mod doctest;
+mod gl;
+mod gltf;
+mod headless;
+mod instances;
+mod mesh;
+mod obj;
+mod particles;
+mod pick;
+mod pipeline;
+mod post;
+mod registry;
+mod scene;
+mod shapes;
+mod source;
+mod sprite;
+mod stats;
+mod step;
+mod target;
+mod text;
+mod tilemap;
+mod trace;
+mod vector;
+
+pub use animation::{Animation, Clip, Playback};
+pub use camera::{Camera, FirstPersonCamera, OrbitCamera, PixelCamera, Ray};
+pub use headless::Headless;
+pub use instances::Instances;
+pub use mesh::{Mesh, MeshError};
+pub use particles::{Emitter, Particles};
+pub use pipeline::{Blend, Cull, Pipeline};
+pub use post::{Effect, Lut};
+pub use registry::{resource_errors, Resource, ResourceError};
+use registry::{Id, Ids, Slots};
+pub use scene::{Scene, SceneNode};
+pub use source::{shader_errors, ShaderError, ShaderStage};
+pub use sprite::{Flip, Sprite, SpriteAtlas, SpriteBatch};
+#[cfg(feature = "log")]
+pub use stats::log_frame_stats;
+pub use stats::{resource_stats, FrameStats, ResourceStats, Usage};
+pub use step::FixedStep;
+pub use target::{Offscreen, RenderTarget};
+pub use text::{Font, Text, TextAlign, TextBuilder};
+pub use tilemap::Tilemap;
+pub use trace::{record, replay_error, stop_recording, Player};
+pub use vector::{Vector, VectorBuilder};
+
 /// A 2D rectangular image.
 ///
 /// ---
@@ -159,76 +216,188 @@ pub mod color {
 pub(super) enum GpuCmd {
     /// Set the background color on the GPU output raster.
     Background(f32, f32, f32),
-    Draw(u32, u32),
-    DrawGraphic(u32, u32, u32),
+    // Draw with a pipeline, or the shader's defaults.
+    Draw(Id, Id, Option<Pipeline>),
+    DrawGraphic(Id, Id, Id, Option<Pipeline>),
     SetCamera(Transform),
-    SetTint(u32, [f32; 4]),
-    RasterId(pix::Raster<pix::rgb::SRgba8>, u32),
-    ShaderId(ShaderBuilder, u32),
-    ShapeId(ShapeBuilder, u32, u32),
-    GroupId(u32),
-    GroupWrite(u32, u32, u32, Transform),
-    GroupWriteTex(u32, u32, u32, Transform, ([f32; 2], [f32; 2])),
+    SetTint(Id, [f32; 4]),
+    RasterId(pix::Raster<pix::rgb::SRgba8>, Id),
+    RasterUpdate(Id, pix::Region, pix::Raster<pix::rgb::SRgba8>),
+    RasterDrop(Id),
+    ShaderId(ShaderBuilder, Id),
+    ShaderDrop(Id),
+    ShapeId(ShapeBuilder, Id, Id),
+    ShapeDrop(Id),
+    GroupId(Id),
+    GroupDrop(Id),
+    GroupWrite(Id, u32, Id, Transform),
+    GroupWriteTex(Id, u32, Id, Transform, ([f32; 2], [f32; 2])),
+    // Write instances of a shape (id, transform, tint) into a group.
+    InstanceWrite(Id, Id, Vec<(u32, Transform, Option<[u8; 4]>)>),
+    Capture(Arc<Mutex<CaptureInternal>>),
+    // Draw into a texture (id, width, height, clear color), or the screen.
+    SetTarget(Option<(Id, u32, u32, [f32; 4])>),
+    // Run effects over everything drawn on the screen.
+    PostProcess(Vec<post::Pass>),
+}
+
+pub(super) struct CaptureInternal {
+    pub(super) waker: Option<Waker>,
+    pub(super) raster: Option<pix::Raster<pix::rgb::SRgba8>>,
+}
+
+// Send a captured raster back to the async thread.
+fn captured(
+    capture: Arc<Mutex<CaptureInternal>>,
+    raster: pix::Raster<pix::rgb::SRgba8>,
+) {
+    let mut capture = capture.lock().unwrap();
+    capture.raster = Some(raster);
+    if let Some(waker) = capture.waker.take() {
+        waker.wake();
+    }
 }
 
 pub(super) struct FrameInternal {
     pub(super) waker: Option<Waker>,
-    pub(super) frame: Option<(std::time::Duration, f32, bool)>,
+    pub(super) frame: Option<(std::time::Duration, f32, bool, u32)>,
 }
 
 type Location = Vec<(usize, usize)>;
 
+// Whether a shader has depth, vertex colors and texture coordinates.
+type Layout = [bool; 3];
+
+// Shader, GL program name, vertex layout and whether it blends.
+type GpuShader = (window::Shader, u32, Layout, bool);
+
+// What the window backend is drawing on.
+struct Drawing {
+    // Camera set by the canvas.
+    camera: Transform,
+    // For offscreen targets: the aspect ratio, window state to restore (see
+    // `gl::unbind()`) and the window's camera.
+    target: Option<(f32, gl::Saved, Transform)>,
+}
+
+impl Drawing {
+    // Get the camera to give the `window` crate, which always projects for
+    // the window's aspect ratio.
+    fn camera(&self, window_aspect: f32) -> Transform {
+        match self.target {
+            None => self.camera,
+            // Project for the target's aspect ratio instead, upside down
+            // because OpenGL textures go from bottom to top.
+            Some((aspect, _, _)) => {
+                Transform::new().translate(0.0, aspect - window_aspect, 0.0)
+                    * self.camera
+                    * Transform::new().scale(1.0, -window_aspect / aspect, 1.0)
+            }
+        }
+    }
+}
+
 pub(super) struct Internal {
     pub(super) cmds: Mutex<Vec<GpuCmd>>,
     pub(super) frame: Mutex<FrameInternal>,
     pub(super) pair: Arc<(Mutex<bool>, Condvar)>,
-    raster_garbage: Mutex<Vec<u32>>,
-    rasters: RefCell<Vec<window::RasterId>>,
-    shader_garbage: Mutex<Vec<u32>>,
-    shaders: RefCell<Vec<window::Shader>>,
-    shape_garbage: Mutex<Vec<u32>>,
-    shapes: RefCell<Vec<window::Shape>>,
-    group_garbage: Mutex<Vec<u32>>,
-    groups: RefCell<Vec<(window::Group, Location)>>,
-}
-static mut INTERNAL: MaybeUninit<Internal> = MaybeUninit::uninit();
-static INIT: Once = Once::new();
-static NEXT_RASTER_ID: AtomicU32 = AtomicU32::new(0);
-static NEXT_SHADER_ID: AtomicU32 = AtomicU32::new(0);
-static NEXT_SHAPE_ID: AtomicU32 = AtomicU32::new(0);
-static NEXT_GROUP_ID: AtomicU32 = AtomicU32::new(0);
+    raster_ids: Mutex<Ids>,
+    shader_ids: Mutex<Ids>,
+    shape_ids: Mutex<Ids>,
+    group_ids: Mutex<Ids>,
+    shader_files: Mutex<HashMap<Id, source::ShaderFiles>>,
+    // Shader sources to free once the commands that use them have run.
+    retired_sources: Mutex<Vec<source::Source>>,
+    shader_errors: Mutex<Vec<ShaderError>>,
+    resource_errors: Mutex<Vec<ResourceError>>,
+    accounting: Mutex<stats::Accounting>,
+    pub(super) frame_stats: Mutex<FrameStats>,
+    // Log stats of frames slower than this.
+    #[cfg(feature = "log")]
+    frame_log: Mutex<Option<std::time::Duration>>,
+    recorder: Mutex<Option<trace::Recorder>>,
+    player: Mutex<Option<Player>>,
+}
+static INTERNAL: OnceLock<Internal> = OnceLock::new();
 
 impl Internal {
     // Get internal graphics data, lazily initializing if not used yet.
     pub(super) fn new_lazy() -> &'static Self {
         // It's in the Condvar docs, so this is the recommended way to do it.
         #[allow(clippy::mutex_atomic)]
-        unsafe {
-            INIT.call_once(|| {
-                INTERNAL = MaybeUninit::new(Internal {
-                    cmds: Mutex::new(Vec::new()),
-                    frame: Mutex::new(FrameInternal {
-                        waker: None,
-                        frame: None,
-                    }),
-                    pair: Arc::new((Mutex::new(false), Condvar::new())),
-                    raster_garbage: Mutex::new(Vec::new()),
-                    rasters: RefCell::new(Vec::new()),
-                    shader_garbage: Mutex::new(Vec::new()),
-                    shaders: RefCell::new(Vec::new()),
-                    shape_garbage: Mutex::new(Vec::new()),
-                    shapes: RefCell::new(Vec::new()),
-                    group_garbage: Mutex::new(Vec::new()),
-                    groups: RefCell::new(Vec::new()),
-                });
-            });
-            &*INTERNAL.as_ptr()
-        }
+        INTERNAL.get_or_init(|| Internal {
+            cmds: Mutex::new(Vec::new()),
+            frame: Mutex::new(FrameInternal {
+                waker: None,
+                frame: None,
+            }),
+            pair: Arc::new((Mutex::new(false), Condvar::new())),
+            raster_ids: Mutex::new(Ids::default()),
+            shader_ids: Mutex::new(Ids::default()),
+            shape_ids: Mutex::new(Ids::default()),
+            group_ids: Mutex::new(Ids::default()),
+            shader_files: Mutex::new(HashMap::new()),
+            retired_sources: Mutex::new(Vec::new()),
+            shader_errors: Mutex::new(Vec::new()),
+            resource_errors: Mutex::new(Vec::new()),
+            accounting: Mutex::new(stats::Accounting::default()),
+            frame_stats: Mutex::new(FrameStats::default()),
+            #[cfg(feature = "log")]
+            frame_log: Mutex::new(None),
+            recorder: Mutex::new(None),
+            player: Mutex::new(None),
+        })
+    }
+
+    // Delete a resource from the GPU with the command made by `cmd`, and
+    // recycle its `id`.
+    fn delete(&self, cmd: fn(Id) -> GpuCmd, ids: &Mutex<Ids>, id: Id) {
+        // Keep the command buffer locked until the id is recycled, so that
+        // the deletion happens before the id is re-used.
+        let mut lock = self.cmds.lock().unwrap();
+        lock.push(cmd(id));
+        ids.lock().unwrap().free(id);
     }
 }
 
+// Resources on the GPU, which only the draw thread uses.
+struct Gpu {
+    rasters: Slots<(window::RasterId, u32)>,
+    shaders: Slots<GpuShader>,
+    // Shaders to build shapes for blending shaders with.
+    layouts: HashMap<Layout, window::Shader>,
+    shapes: Slots<window::Shape>,
+    // Faces and shader of shapes with vertex colors, for tinting.
+    shape_sources: HashMap<Id, (ShapeBuilder, Id)>,
+    // Shapes with their vertex colors tinted, and whether they were used this
+    // frame (the ones that weren't are dropped at the end of it).
+    tinted: HashMap<(Id, [u8; 4]), (window::Shape, bool)>,
+    groups: Slots<(window::Group, Location)>,
+    framebuffers: HashMap<Id, gl::Framebuffer>,
+    drawing: Drawing,
+    screen: post::Screen,
+}
+
+thread_local! {
+    static GPU: RefCell<Gpu> = RefCell::new(Gpu {
+        rasters: Slots::new(Resource::Texture),
+        shaders: Slots::new(Resource::Shader),
+        layouts: HashMap::new(),
+        shapes: Slots::new(Resource::Shape),
+        shape_sources: HashMap::new(),
+        tinted: HashMap::new(),
+        groups: Slots::new(Resource::Group),
+        framebuffers: HashMap::new(),
+        drawing: Drawing {
+            camera: Transform::new(),
+            target: None,
+        },
+        screen: post::Screen::default(),
+    });
+}
+
 /// `Raster` stored on the GPU.
-pub struct Texture(pub(super) u32);
+pub struct Texture(pub(super) Id);
 
 impl Texture {
     /// Create a `Texture` by copying a `Raster` to the GPU.
@@ -237,67 +406,114 @@ impl Texture {
         pix::chan::Ch8: From<<P as pix::el::Pixel>::Chan>,
     {
         let internal = Internal::new_lazy();
-        let id = if let Some(id) = internal.raster_garbage.lock().unwrap().pop()
-        {
-            id
-        } else {
-            NEXT_RASTER_ID.fetch_add(1, Ordering::Relaxed)
-        };
+        let id = internal.raster_ids.lock().unwrap().alloc();
         let raster = pix::Raster::<pix::rgb::SRgba8>::with_raster(&raster);
         let mut lock = internal.cmds.lock().unwrap();
         lock.push(GpuCmd::RasterId(raster, id));
         Texture(id)
     }
+
+    /// Copy a `Raster` into a `region` of this `Texture` on the GPU, without
+    /// re-creating it.  Parts of the `region` outside of the `Texture` or the
+    /// `Raster` are left unchanged.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Headless, PixelCamera, Region, Texture,
+    ///     Tilemap,
+    /// };
+    /// use cala::task::exec;
+    /// use cala::video::{rgb::SRgba8, Raster};
+    /// use cala::window::Frame;
+    /// use std::sync::Arc;
+    ///
+    /// let red = SRgba8::new(255, 0, 0, 255);
+    /// let green = SRgba8::new(0, 255, 0, 255);
+    /// std::thread::spawn(move || {
+    ///     let mut texture = Texture::new(&Raster::with_color(2, 1, red));
+    ///     let raster = Raster::with_color(1, 1, green);
+    ///     texture.update(Region::new(1, 0, 1, 1), &raster);
+    ///     // Completely outside of the texture, so nothing changes.
+    ///     texture.update(Region::new(5, -3, 1, 1), &raster);
+    ///     let texture = Arc::new(texture);
+    ///     let mut map = Tilemap::new(texture, (1, 1), (1, 1), 1, 8.0);
+    ///     map.set(0, 0, 0, Some(0));
+    ///     let camera = PixelCamera::new();
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///         frame.draw_tilemap(&camera, &mut map);
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// assert_eq!(gpu.raster().pixel(1, 1), red);
+    /// assert_eq!(gpu.raster().pixel(6, 1), green);
+    /// ```
+    pub fn update<P: pix::el::Pixel>(
+        &mut self,
+        region: pix::Region,
+        raster: &pix::Raster<P>,
+    ) where
+        pix::chan::Ch8: From<<P as pix::el::Pixel>::Chan>,
+    {
+        let region = pix::Region::new(
+            region.left(),
+            region.top(),
+            region.width().min(raster.width()),
+            region.height().min(raster.height()),
+        );
+        let raster = pix::Raster::<pix::rgb::SRgba8>::with_raster(raster);
+        let internal = Internal::new_lazy();
+        let mut lock = internal.cmds.lock().unwrap();
+        lock.push(GpuCmd::RasterUpdate(self.0, region, raster));
+    }
 }
 
 impl Drop for Texture {
     fn drop(&mut self) {
-        // FIXME: Make GpuCmd
         let internal = Internal::new_lazy();
-        internal.raster_garbage.lock().unwrap().push(self.0);
+        internal.delete(GpuCmd::RasterDrop, &internal.raster_ids, self.0);
     }
 }
 
 /// A Shader.
-pub struct Shader(pub(super) u32);
+// Also whether its vertices have depth, and how many floats each has.
+pub struct Shader(pub(super) Id, (bool, usize));
 
 impl Shader {
     /// Copy and send a shader program to the GPU.
     pub fn new(builder: ShaderBuilder) -> Shader {
         let internal = Internal::new_lazy();
-        let id = if let Some(id) = internal.shader_garbage.lock().unwrap().pop()
-        {
-            id
-        } else {
-            NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed)
-        };
+        let id = internal.shader_ids.lock().unwrap().alloc();
         let mut lock = internal.cmds.lock().unwrap();
+        let format = (builder.depth, stride(&builder));
         lock.push(GpuCmd::ShaderId(builder, id));
-        Shader(id)
+        Shader(id, format)
     }
 }
 
 impl Drop for Shader {
     fn drop(&mut self) {
-        // FIXME: Make GpuCmd
+        source::forget(self.0);
         let internal = Internal::new_lazy();
-        internal.shader_garbage.lock().unwrap().push(self.0);
+        internal.delete(GpuCmd::ShaderDrop, &internal.shader_ids, self.0);
     }
 }
 
 /// A Shape.
-pub struct Shape(u32);
+pub struct Shape(Id, pick::Bounds);
 
 impl Drop for Shape {
     fn drop(&mut self) {
-        // FIXME: Make GpuCmd
         let internal = Internal::new_lazy();
-        internal.shape_garbage.lock().unwrap().push(self.0);
+        internal.delete(GpuCmd::ShapeDrop, &internal.shape_ids, self.0);
     }
 }
 
 /// A Group.
-pub struct Group(pub(crate) u32);
+// Also the bounds and transform of each shape written to it, for picking.
+pub struct Group(pub(crate) Id, Vec<Option<(pick::Bounds, Transform)>>);
 
 impl Default for Group {
     fn default() -> Self {
@@ -309,19 +525,15 @@ impl Group {
     /// Create a new Group of Shapes.
     pub fn new() -> Self {
         let internal = Internal::new_lazy();
-        let id = if let Some(id) = internal.group_garbage.lock().unwrap().pop()
-        {
-            id
-        } else {
-            NEXT_GROUP_ID.fetch_add(1, Ordering::Relaxed)
-        };
+        let id = internal.group_ids.lock().unwrap().alloc();
         let mut lock = internal.cmds.lock().unwrap();
         lock.push(GpuCmd::GroupId(id));
-        Group(id)
+        Group(id, Vec::new())
     }
 
-    /// Push a shape into the group.
+    /// Push a shape into the group.  Ids that are skipped over are left empty.
     pub fn write(&mut self, id: u32, shape: &Shape, transform: &Transform) {
+        self.record(id, shape.1, transform);
         let internal = Internal::new_lazy();
         let mut lock = internal.cmds.lock().unwrap();
         lock.push(GpuCmd::GroupWrite(self.0, id, shape.0, *transform));
@@ -335,6 +547,7 @@ impl Group {
         transform: &Transform,
         tex_coords: ([f32; 2], [f32; 2]),
     ) {
+        self.record(id, shape.1, transform);
         let internal = Internal::new_lazy();
         let mut lock = internal.cmds.lock().unwrap();
         lock.push(GpuCmd::GroupWriteTex(
@@ -345,22 +558,149 @@ impl Group {
 
 impl Drop for Group {
     fn drop(&mut self) {
-        // FIXME: Make GpuCmd
         let internal = Internal::new_lazy();
-        internal.group_garbage.lock().unwrap().push(self.0);
+        internal.delete(GpuCmd::GroupDrop, &internal.group_ids, self.0);
     }
 }
 
 static ASPECT: AtomicU32 = AtomicU32::new(0);
+static WIDTH: AtomicU32 = AtomicU32::new(0);
+
+// Get the column-major 4x4 matrix out of a `Transform`.
+fn mat4(transform: Transform) -> [[f32; 4]; 4] {
+    // `Transform` is a `#[repr(C)]` wrapper around a 4x4 matrix.
+    unsafe { std::mem::transmute(transform) }
+}
+
+// Copy `raster` into `region` of a texture's RGBA pixels, clipping to the
+// texture.  `region` must not be larger than `raster`.
+fn blit(
+    pixels: &mut [u8],
+    width: u32,
+    region: pix::Region,
+    raster: &pix::Raster<pix::rgb::SRgba8>,
+) {
+    let height = (pixels.len() / 4) as u32 / width;
+    let clip = region.intersection(pix::Region::new(0, 0, width, height));
+    if clip.width() == 0 || clip.height() == 0 {
+        return;
+    }
+    let stride = width as usize * 4;
+    let src_stride = raster.width() as usize * 4;
+    let src_x = (clip.left() - region.left()) as usize * 4;
+    let len = clip.width() as usize * 4;
+    let source = raster.as_u8_slice();
+    for y in clip.top()..clip.bottom() {
+        let dst = y as usize * stride + clip.left() as usize * 4;
+        let src = (y - region.top()) as usize * src_stride + src_x;
+        pixels[dst..dst + len].copy_from_slice(&source[src..src + len]);
+    }
+}
+
+// Build a shape on the GPU, with its vertex colors multiplied by `tint`.
+fn build_shape(
+    shaders: &mut Slots<GpuShader>,
+    layouts: &mut HashMap<Layout, window::Shader>,
+    shader: Id,
+    builder: &ShapeBuilder,
+    tint: Option<[u8; 4]>,
+) -> Result<window::Shape, ResourceError> {
+    let (shader, _name, layout, blend) = shaders.get_mut(shader)?;
+    let [depth, gradient, graphic] = *layout;
+    let components = match (gradient, *blend) {
+        (false, _) => 0,
+        (true, false) => 3,
+        (true, true) => 4,
+    };
+    let stride =
+        if depth { 3 } else { 2 } + if graphic { 2 } else { 0 } + components;
+    // Shapes for blending shaders are built with a placeholder shader (see
+    // `ShaderId`).
+    let shader = if *blend {
+        layouts.get_mut(layout).unwrap()
+    } else {
+        shader
+    };
+    let mut shape = window::ShapeBuilder::new(shader);
+    for face in builder.faces.iter() {
+        if let Some(ref vertices) = face.vertices {
+            shape = match tint {
+                Some(tint) => {
+                    let mut vertices = vertices.clone();
+                    for vertex in vertices.chunks_exact_mut(stride) {
+                        let colors = &mut vertex[stride - components..];
+                        for (color, tint) in colors.iter_mut().zip(tint) {
+                            *color *= f32::from(tint) / 255.0;
+                        }
+                    }
+                    shape.vert(vertices.as_slice())
+                }
+                None => shape.vert(vertices.as_slice()),
+            };
+        }
+        if let Some(transform) = face.transform {
+            shape = shape.face(transform);
+        }
+    }
+    Ok(shape.finish())
+}
+
+// Get the number of floats in each vertex of a shader's shapes.
+fn stride(builder: &ShaderBuilder) -> usize {
+    let components = match (builder.gradient, builder.blend) {
+        (false, _) => 0,
+        (true, false) => 3,
+        (true, true) => 4,
+    };
+    let position = if builder.depth { 3 } else { 2 };
+    let tex = if builder.graphic { 2 } else { 0 };
+    position + tex + components
+}
+
+// Write a shape into a group with `write`, after the shape before it.  Slots
+// that were skipped over are left empty.
+fn write<F>(group: &mut (window::Group, Location), id: u32, write: F)
+where
+    F: FnOnce(&mut window::Group, (usize, usize)) -> (usize, usize),
+{
+    let (group, locations) = group;
+    let id = id as usize;
+    let start = locations[..id.min(locations.len())]
+        .last()
+        .copied()
+        .unwrap_or((0, 0));
+    if id >= locations.len() {
+        locations.resize(id + 1, start);
+    }
+    locations[id] = write(group, start);
+}
+
+// Something that can process commands from the command buffer.
+pub(super) trait Backend {
+    // Return the aspect ratio (`height / width`) of the output.
+    fn aspect(&self) -> f32;
+    // Return the width of the output in pixels.
+    fn width(&self) -> u32;
+    // Run a command from the command buffer, failing if it uses a resource
+    // that was dropped.
+    fn execute(&mut self, cmd: GpuCmd) -> Result<(), ResourceError>;
+    // Free anything that was only needed for the frame that just ran.
+    fn end_frame(&mut self) {}
+}
+
+// A function that is run on the graphics thread whenever a frame is requested.
+fn async_runner<B: Backend>(backend: &mut B, elapsed: std::time::Duration) {
+    // Reload shaders that changed on disk.
+    source::poll();
 
-// A function that is run on the graphics thread whenever
-fn async_runner(window: &mut window::Window, elapsed: std::time::Duration) {
     // Get the aspect ratio
-    let aspect = window.aspect();
+    let aspect = backend.aspect();
     // Check if the window has been resized.
     let new_aspect = u32::from_ne_bytes(aspect.to_ne_bytes());
     let old_aspect = ASPECT.swap(new_aspect, Ordering::Relaxed);
-    let resized = new_aspect != old_aspect;
+    let width = backend.width();
+    let old_width = WIDTH.swap(width, Ordering::Relaxed);
+    let resized = new_aspect != old_aspect || width != old_width;
 
     // Reset condvar
     let pair = {
@@ -370,47 +710,110 @@ fn async_runner(window: &mut window::Window, elapsed: std::time::Duration) {
     let (lock, cvar) = &*pair;
     *lock.lock().unwrap() = false;
 
-    // Wake async thread
+    // Wake async thread (if it's already waiting on a frame)
     {
         let internal = Internal::new_lazy();
         let mut lock = internal.frame.lock().unwrap();
-        lock.waker.take().unwrap().wake();
-        lock.frame = Some((elapsed, aspect, resized));
+        lock.frame = Some((elapsed, aspect, resized, width));
+        if let Some(waker) = lock.waker.take() {
+            waker.wake();
+        }
     }
 
     // Wait for async thread to finish writing to the command buffer.
+    let waiting = Instant::now();
     let mut started = lock.lock().unwrap();
     while !*started {
         started = cvar.wait(started).unwrap();
     }
+    let mut frame = FrameStats {
+        waiting: waiting.elapsed(),
+        ..FrameStats::default()
+    };
 
     // Process commands in the command buffer.
-    let mut lock = Internal::new_lazy().cmds.lock().unwrap();
-    for cmd in lock.drain(..) {
+    let (cmds, retired) = {
+        let mut cmds = Internal::new_lazy().cmds.lock().unwrap();
+        let drained: Vec<GpuCmd> = cmds.drain(..).collect();
+        (drained, source::retired())
+    };
+    trace::frame(elapsed, aspect, resized, &cmds);
+    let mut accounting = Internal::new_lazy().accounting.lock().unwrap();
+    for cmd in cmds.iter() {
+        accounting.account(cmd, &mut frame);
+    }
+    drop(accounting);
+    let processing = Instant::now();
+    for cmd in cmds {
+        if let Err(error) = backend.execute(cmd) {
+            registry::report(error);
+        }
+    }
+    // Nothing uses the retired shader sources anymore.
+    drop(retired);
+    backend.end_frame();
+    frame.processing = processing.elapsed();
+    stats::rendered(frame);
+}
+
+impl Backend for window::Window {
+    fn aspect(&self) -> f32 {
+        window::Window::aspect(self)
+    }
+
+    fn width(&self) -> u32 {
+        gl::width()
+    }
+
+    fn execute(&mut self, cmd: GpuCmd) -> Result<(), ResourceError> {
+        GPU.with(|gpu| gpu.borrow_mut().execute(self, cmd))
+    }
+
+    fn end_frame(&mut self) {
+        GPU.with(|gpu| {
+            let mut gpu = gpu.borrow_mut();
+            gpu.tinted
+                .retain(|_, (_, used)| std::mem::replace(used, false));
+        })
+    }
+}
+
+impl Gpu {
+    fn execute(
+        &mut self,
+        window: &mut window::Window,
+        cmd: GpuCmd,
+    ) -> Result<(), ResourceError> {
         use GpuCmd::*;
         match cmd {
             Background(r, g, b) => window.background(r, g, b),
-            Draw(shader, group) => {
-                let shaders = Internal::new_lazy().shaders.borrow();
-                let groups = Internal::new_lazy().groups.borrow();
-                window
-                    .draw(&shaders[shader as usize], &groups[group as usize].0);
+            Draw(shader, group, pipeline) => {
+                let shader = self.shaders.get(shader)?;
+                let group = self.groups.get(group)?;
+                let pipeline = Pipeline::or_shader(pipeline, shader.3);
+                gl::pipeline(&pipeline);
+                window.draw(&shader.0, &group.0);
+                if !pipeline.depth_write {
+                    gl::depth_write();
+                }
             }
-            DrawGraphic(shader, group, raster) => {
-                let shaders = Internal::new_lazy().shaders.borrow();
-                let groups = Internal::new_lazy().groups.borrow();
-                window.draw_graphic(
-                    &shaders[shader as usize],
-                    &groups[group as usize].0,
-                    &Internal::new_lazy().rasters.borrow()[raster as usize],
-                );
+            DrawGraphic(shader, group, raster, pipeline) => {
+                let shader = self.shaders.get(shader)?;
+                let group = self.groups.get(group)?;
+                let raster = self.rasters.get(raster)?;
+                let pipeline = Pipeline::or_shader(pipeline, shader.3);
+                gl::pipeline(&pipeline);
+                window.draw_graphic(&shader.0, &group.0, &raster.0);
+                if !pipeline.depth_write {
+                    gl::depth_write();
+                }
             }
             SetCamera(camera) => {
-                window.camera(camera);
+                self.drawing.camera = camera;
+                window.camera(self.drawing.camera(window.aspect()));
             }
             SetTint(shader, tint) => {
-                let shaders = Internal::new_lazy().shaders.borrow();
-                window.tint(&shaders[shader as usize], tint);
+                window.tint(&self.shaders.get(shader)?.0, tint);
             }
             RasterId(raster, id) => {
                 let gpu_raster = window.graphic(
@@ -418,115 +821,180 @@ fn async_runner(window: &mut window::Window, elapsed: std::time::Duration) {
                     raster.width() as usize,
                     raster.height() as usize,
                 );
-                let mut rasters = Internal::new_lazy().rasters.borrow_mut();
-                if id as usize == rasters.len() {
-                    rasters.push(gpu_raster);
-                } else {
-                    rasters[id as usize] = gpu_raster;
-                }
+                // The new texture is left bound.
+                let name = gl::texture_binding();
+                self.rasters.store(id, (gpu_raster, name));
+            }
+            RasterUpdate(id, region, raster) => {
+                window.update_graphic(
+                    &mut self.rasters.get_mut(id)?.0,
+                    &mut |pixels, width| {
+                        blit(pixels, width.into(), region, &raster)
+                    },
+                );
+            }
+            RasterDrop(id) => {
+                self.framebuffers.remove(&id);
+                let (_raster, name) = self.rasters.remove(id)?;
+                gl::delete_texture(name);
             }
-            ShaderId(shader, id) => {
+            ShaderId(mut shader, id) => {
+                let old = self.shaders.get(id).is_ok();
+                if let Err((stage, log)) = gl::compile(&shader) {
+                    source::report(id, stage, log);
+                    // Keep drawing with the old source, or draw magenta.
+                    if old {
+                        return Ok(());
+                    }
+                    shader = gl::placeholder(&shader);
+                }
+                // Blending is set by `gl::pipeline()` instead, but the
+                // `window` crate also uses it to choose the vertex layout of
+                // shapes, so those are built with a placeholder shader.
+                let layout = [shader.depth, shader.gradient, shader.graphic];
+                let blend = shader.blend;
+                if blend {
+                    self.layouts.entry(layout).or_insert_with(|| {
+                        window.shader_new(gl::placeholder(&shader))
+                    });
+                    shader.blend = false;
+                }
                 let shader = window.shader_new(shader);
-                let mut shaders = Internal::new_lazy().shaders.borrow_mut();
-                if id as usize == shaders.len() {
-                    shaders.push(shader);
-                } else {
-                    shaders[id as usize] = shader;
+                // The new program is left in use.
+                let name = gl::current_program();
+                // Replace the old program when reloading.
+                if old {
+                    gl::delete_program(self.shaders.get(id)?.1);
                 }
+                self.shaders.store(id, (shader, name, layout, blend));
+            }
+            ShaderDrop(id) => {
+                let (_shader, name, _layout, _blend) =
+                    self.shaders.remove(id)?;
+                gl::delete_program(name);
             }
             ShapeId(shape_builder, id, shader) => {
-                let mut shapes = Internal::new_lazy().shapes.borrow_mut();
-                let mut shaders = Internal::new_lazy().shaders.borrow_mut();
-                let mut shape =
-                    window::ShapeBuilder::new(&mut shaders[shader as usize]);
-                for face in shape_builder.faces {
-                    if let Some(vertices) = face.vertices {
-                        shape = shape.vert(vertices.as_slice());
-                    }
-                    if let Some(transform) = face.transform {
-                        shape = shape.face(transform);
-                    }
-                }
-                if id as usize == shapes.len() {
-                    shapes.push(shape.finish());
+                let shape = build_shape(
+                    &mut self.shaders,
+                    &mut self.layouts,
+                    shader,
+                    &shape_builder,
+                    None,
+                )?;
+                self.shapes.store(id, shape);
+                let [_depth, gradient, _graphic] = self.shaders.get(shader)?.2;
+                if gradient {
+                    self.shape_sources.insert(id, (shape_builder, shader));
                 } else {
-                    shapes[id as usize] = shape.finish();
+                    self.shape_sources.remove(&id);
                 }
             }
+            ShapeDrop(id) => {
+                self.shapes.remove(id)?;
+                self.shape_sources.remove(&id);
+                self.tinted.retain(|(shape, _tint), _| *shape != id);
+            }
             GroupId(id) => {
-                let mut groups = Internal::new_lazy().groups.borrow_mut();
-                if id as usize == groups.len() {
-                    groups.push((window.group_new(), Vec::new()));
-                } else {
-                    groups[id as usize] = (window.group_new(), Vec::new());
-                }
+                self.groups.store(id, (window.group_new(), Vec::new()));
             }
             GroupWrite(group, id, shape, transform) => {
-                let mut groups = Internal::new_lazy().groups.borrow_mut();
-                let shapes = Internal::new_lazy().shapes.borrow();
-                if id >= groups[group as usize].1.len() as u32 {
-                    let location = if id == 0 {
-                        (0, 0)
-                    } else {
-                        groups[group as usize].1[id as usize - 1]
-                    };
-                    let location = groups[group as usize].0.write(
-                        location,
-                        &shapes[shape as usize],
-                        &transform,
-                    );
-                    groups[group as usize].1.push(location);
-                } else {
-                    let location = if id == 0 {
-                        (0, 0)
-                    } else {
-                        groups[group as usize].1[id as usize - 1]
-                    };
-                    groups[group as usize].1[id as usize] = groups
-                        [group as usize]
-                        .0
-                        .write(location, &shapes[shape as usize], &transform);
-                }
+                let group = self.groups.get_mut(group)?;
+                let shape = self.shapes.get(shape)?;
+                write(group, id, |group, at| {
+                    group.write(at, shape, &transform)
+                });
             }
             GroupWriteTex(group, id, shape, transform, texcoords) => {
-                let mut groups = Internal::new_lazy().groups.borrow_mut();
-                let shapes = Internal::new_lazy().shapes.borrow();
-                if id >= groups[group as usize].1.len() as u32 {
-                    let location = if id == 0 {
-                        (0, 0)
-                    } else {
-                        groups[group as usize].1[id as usize - 1]
-                    };
-                    let location = groups[group as usize].0.write_tex(
-                        location,
-                        &shapes[shape as usize],
-                        &transform,
-                        texcoords,
-                    );
-                    groups[group as usize].1.push(location);
-                } else {
-                    let location = if id == 0 {
-                        (0, 0)
-                    } else {
-                        groups[group as usize].1[id as usize - 1]
+                let group = self.groups.get_mut(group)?;
+                let shape = self.shapes.get(shape)?;
+                write(group, id, |group, at| {
+                    group.write_tex(at, shape, &transform, texcoords)
+                });
+            }
+            InstanceWrite(group, shape, instances) => {
+                let group = self.groups.get_mut(group)?;
+                let source = self.shape_sources.get(&shape);
+                for (id, transform, tint) in instances {
+                    let instance = match (tint, source) {
+                        (Some(tint), Some((builder, shader))) => {
+                            let tinted = match self.tinted.entry((shape, tint))
+                            {
+                                Entry::Occupied(entry) => entry.into_mut(),
+                                Entry::Vacant(entry) => {
+                                    let tinted = build_shape(
+                                        &mut self.shaders,
+                                        &mut self.layouts,
+                                        *shader,
+                                        builder,
+                                        Some(tint),
+                                    )?;
+                                    entry.insert((tinted, false))
+                                }
+                            };
+                            tinted.1 = true;
+                            &tinted.0
+                        }
+                        _ => self.shapes.get(shape)?,
                     };
-                    groups[group as usize].1[id as usize] =
-                        groups[group as usize].0.write_tex(
-                            location,
-                            &shapes[shape as usize],
-                            &transform,
-                            texcoords,
-                        );
+                    write(group, id, |group, at| {
+                        group.write(at, instance, &transform)
+                    });
                 }
             }
+            GroupDrop(id) => {
+                // Dropping a `window::Group` deletes its buffers.
+                self.groups.remove(id)?;
+            }
+            Capture(capture) => captured(capture, gl::read_pixels()),
+            SetTarget(target) => {
+                if let Some((_, saved, camera)) = self.drawing.target.take() {
+                    gl::unbind(saved);
+                    self.drawing.camera = camera;
+                }
+                if let Some((id, width, height, color)) = target {
+                    if !self.framebuffers.contains_key(&id) {
+                        let name = self.rasters.get(id)?.1;
+                        let framebuffer =
+                            gl::Framebuffer::new(name, width, height);
+                        self.framebuffers.insert(id, framebuffer);
+                    }
+                    let saved = self.framebuffers[&id].bind(color);
+                    let aspect = height as f32 / width as f32;
+                    let camera = self.drawing.camera;
+                    self.drawing.target = Some((aspect, saved, camera));
+                    self.drawing.camera = Transform::new();
+                }
+                window.camera(self.drawing.camera(window.aspect()));
+            }
+            PostProcess(passes) => {
+                let luts = passes
+                    .iter()
+                    .map(|pass| match pass {
+                        post::Pass::ColorGrade(lut, _) => {
+                            Ok(Some(self.rasters.get(*lut)?.1))
+                        }
+                        _ => Ok(None),
+                    })
+                    .collect::<Result<Vec<_>, ResourceError>>()?;
+                self.screen.run(window, &passes, &luts);
+                window.camera(self.drawing.camera(window.aspect()));
+            }
         }
+        Ok(())
     }
 }
 
 /// Run the infinite event loop.  You should only call this on the main thread.
 pub fn draw_thread() {
-    let fallback_window_title = env!("CARGO_PKG_NAME");
-    let mut window = window::Window::new(fallback_window_title, async_runner);
+    draw_thread_with(crate::window::WindowConfig::new());
+}
+
+/// Run the infinite event loop in a window opened with `config`.  You should
+/// only call this on the main thread.
+pub fn draw_thread_with(config: crate::window::WindowConfig) {
+    let mut window =
+        window::Window::new(&config.title, async_runner::<window::Window>);
+    gl::swap_interval(config.vsync);
     loop {
         window.run();
     }
@@ -588,16 +1056,13 @@ impl ShapeBuilder {
 
     /// Finish building the shape.
     pub fn finish(self, shader: &Shader) -> Shape {
+        let (depth, stride) = shader.1;
+        let bounds = pick::Bounds::new(&self, depth, stride);
         let internal = Internal::new_lazy();
-        let id = if let Some(id) = internal.shape_garbage.lock().unwrap().pop()
-        {
-            id
-        } else {
-            NEXT_SHAPE_ID.fetch_add(1, Ordering::Relaxed)
-        };
+        let id = internal.shape_ids.lock().unwrap().alloc();
         let mut lock = internal.cmds.lock().unwrap();
         lock.push(GpuCmd::ShapeId(self, id, shader.0));
-        Shape(id)
+        Shape(id, bounds)
     }
 }
 
@@ -605,8 +1070,32 @@ impl ShapeBuilder {
 pub trait Canvas {
     /// Draw a group on the screen.
     fn draw(&mut self, shader: &Shader, group: &Group);
+    /// Draw a group on the screen with a [`Pipeline`].
+    ///
+    /// The default implementation ignores the pipeline and just calls
+    /// [`draw()`](Canvas::draw), so canvases that support pipelines must
+    /// override it.
+    fn draw_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        pipeline: Pipeline,
+    ) {
+        let _ = pipeline;
+        self.draw(shader, group);
+    }
     /// Set camera for shader.
     fn set_camera(&mut self, camera: Transform);
+    /// Set the camera to a [`Camera`], for the current size of the canvas.
+    fn use_camera<C: Camera>(&mut self, camera: &C) {
+        let transform = camera.transform(self.height(), self.pixel_width());
+        self.set_camera(transform);
+    }
+    /// Get the ray from a [`Camera`] through a `point` on the canvas (X from
+    /// 0 to 1, Y from 0 to [`height()`](Canvas::height)).
+    fn unproject<C: Camera>(&self, camera: &C, point: [f32; 2]) -> Ray {
+        camera.unproject(self.height(), self.pixel_width(), point)
+    }
     /// Set tint for shader.
     fn set_tint<P: pix::el::Pixel>(&mut self, shader: &Shader, tint: P)
     where
@@ -618,10 +1107,74 @@ pub trait Canvas {
         group: &Group,
         graphic: &Texture,
     );
+    /// Draw a group with a texture on the screen with a [`Pipeline`].
+    ///
+    /// The default implementation ignores the pipeline and just calls
+    /// [`draw_graphic()`](Canvas::draw_graphic), so canvases that support
+    /// pipelines must override it.
+    fn draw_graphic_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        graphic: &Texture,
+        pipeline: Pipeline,
+    ) {
+        let _ = pipeline;
+        self.draw_graphic(shader, group, graphic);
+    }
     /// Returns the amount of time elapsed since the previous frame.
     fn elapsed(&self) -> std::time::Duration;
     /// Return the aspect ratio (`height / width`) of the `Canvas`.
     fn height(&self) -> f32;
     /// Returns true if the canvas has changed size since the last redraw.
     fn resized(&self) -> bool;
+    /// Return the width of the `Canvas` in pixels (by default, the width of
+    /// the window).
+    fn pixel_width(&self) -> u32 {
+        WIDTH.load(Ordering::Relaxed)
+    }
+    /// Draw text laid out with a [`TextBuilder`], tinted with its color.
+    fn draw_text(&mut self, font: &mut Font, text: &mut Text) {
+        let (shader, texture) = text.prepare(font);
+        self.set_tint(shader, text.color());
+        self.draw_graphic(shader, text.group(), texture);
+    }
+    /// Draw instances of a shape, sending the instances that changed to the
+    /// GPU first.
+    fn draw_instances(&mut self, shader: &Shader, instances: &mut Instances) {
+        self.draw(shader, instances.group());
+    }
+    /// Draw the shapes of a scene, sending the nodes that moved to the GPU
+    /// first.
+    fn draw_scene(&mut self, shader: &Shader, scene: &mut Scene) {
+        self.draw(shader, scene.group());
+    }
+    /// Draw the particles of a particle system, with its texture if it has
+    /// one.
+    fn draw_particles(&mut self, shader: &Shader, particles: &mut Particles) {
+        let texture = particles.texture.clone();
+        let group = particles.group();
+        match texture {
+            Some(texture) => self.draw_graphic(shader, group, &texture),
+            None => self.draw(shader, group),
+        }
+    }
+    /// Draw the chunks of a tilemap that can be seen through a `camera`
+    /// (which becomes the canvas's camera), sending the tiles that changed to
+    /// the GPU first.
+    fn draw_tilemap<C: Camera>(&mut self, camera: &C, tilemap: &mut Tilemap) {
+        self.use_camera(camera);
+        let height = self.height();
+        let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, height], [1.0, height]]
+            .map(|point| self.unproject(camera, point));
+        let (shader, texture, groups) = tilemap.prepare(&corners);
+        for group in groups {
+            self.draw_graphic(shader, group, texture);
+        }
+    }
+    /// Draw a batch of sprites from an atlas, emptying the batch.
+    fn draw_sprites(&mut self, atlas: &SpriteAtlas, batch: &mut SpriteBatch) {
+        let (shader, texture) = batch.prepare(atlas);
+        self.draw_graphic(shader, batch.group(), texture);
+    }
 }
@@ -0,0 +1,236 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{Canvas, Sprite, SpriteAtlas};
+use std::{sync::Arc, time::Duration};
+
+/// What an [`Animation`] does after the last frame of its [`Clip`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Playback {
+    /// Stop on the last frame.
+    Once,
+    /// Start again from the first frame.
+    Loop,
+    /// Play backwards to the first frame, then forwards again.
+    PingPong,
+}
+
+/// A sequence of sprites from a [`SpriteAtlas`], each shown for its own
+/// duration, played with an [`Animation`].
+///
+/// Clips can have events on frames (numbered from 0), which are returned
+/// when an animation gets to those frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clip {
+    frames: Vec<(Sprite, Duration)>,
+    playback: Playback,
+    // Frame and event.
+    events: Vec<(usize, u32)>,
+}
+
+impl Clip {
+    /// Create a clip without frames yet.
+    pub fn new(playback: Playback) -> Self {
+        Clip {
+            frames: Vec::new(),
+            playback,
+            events: Vec::new(),
+        }
+    }
+
+    /// Add a frame that shows a `sprite` for `duration`.
+    pub fn frame(mut self, sprite: Sprite, duration: Duration) -> Self {
+        assert!(duration > Duration::from_secs(0));
+        self.frames.push((sprite, duration));
+        self
+    }
+
+    /// Add an `event` (any number, like an enum cast to `u32`) to a frame
+    /// that's been added.
+    pub fn event(mut self, frame: usize, event: u32) -> Self {
+        assert!(frame < self.frames.len());
+        self.events.push((frame, event));
+        self
+    }
+
+    /// Get the number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if the clip has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the total duration of the frames, played once.
+    pub fn duration(&self) -> Duration {
+        self.frames
+            .iter()
+            .map(|(_sprite, duration)| *duration)
+            .sum()
+    }
+}
+
+/// A [`Clip`] being played, advanced by the time elapsed between frames of a
+/// [`Canvas`].
+///
+/// ```rust
+/// use cala::graphics::{Animation, Clip, Playback, SpriteAtlas};
+/// use cala::video::{rgb::SRgba8, Raster};
+/// use std::{sync::Arc, time::Duration};
+///
+/// const STEP: u32 = 1;
+///
+/// let mut atlas = SpriteAtlas::new();
+/// let mut sprite = |r, g, b| {
+///     let raster = Raster::with_color(1, 1, SRgba8::new(r, g, b, 255));
+///     atlas.add(&raster)
+/// };
+/// let red = sprite(255, 0, 0);
+/// let green = sprite(0, 255, 0);
+/// let blue = sprite(0, 0, 255);
+/// let ms = Duration::from_millis;
+/// let walk = Clip::new(Playback::PingPong)
+///     .frame(red, ms(100))
+///     .frame(green, ms(100))
+///     .frame(blue, ms(50))
+///     .event(1, STEP);
+/// let mut animation = Animation::new(Arc::new(walk));
+/// assert_eq!(animation.advance_by(ms(90)), []);
+/// assert_eq!(animation.sprite(), red);
+/// assert_eq!(animation.advance_by(ms(20)), [STEP]);
+/// assert_eq!(animation.sprite(), green);
+/// // Back from blue to green.
+/// assert_eq!(animation.advance_by(ms(150)), [STEP]);
+/// assert_eq!(animation.frame(), 1);
+/// // Texture coordinates for `Group::write_tex()`.
+/// assert_eq!(animation.tex_coords(&atlas), atlas.uv(green));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Animation {
+    clip: Arc<Clip>,
+    frame: usize,
+    // Time spent on the current frame.
+    time: Duration,
+    // Whether a ping-pong clip is playing backwards.
+    backwards: bool,
+    finished: bool,
+    // Whether the events of the first frame haven't been returned yet.
+    starting: bool,
+}
+
+impl Animation {
+    /// Start playing a clip from its first frame.  The clip must have
+    /// frames.
+    pub fn new(clip: Arc<Clip>) -> Self {
+        assert!(!clip.is_empty());
+        Animation {
+            clip,
+            frame: 0,
+            time: Duration::from_secs(0),
+            backwards: false,
+            finished: false,
+            starting: true,
+        }
+    }
+
+    /// Get the clip being played.
+    pub fn clip(&self) -> &Clip {
+        &self.clip
+    }
+
+    /// Play a different clip from its first frame, or restart the same one.
+    pub fn play(&mut self, clip: Arc<Clip>) {
+        *self = Animation::new(clip);
+    }
+
+    /// Get the number of the frame being shown.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Get the sprite of the frame being shown.
+    pub fn sprite(&self) -> Sprite {
+        self.clip.frames[self.frame].0
+    }
+
+    /// Get the texture coordinates (offset and size) of the frame being
+    /// shown, for [`Group::write_tex()`](super::Group::write_tex).
+    pub fn tex_coords(&self, atlas: &SpriteAtlas) -> ([f32; 2], [f32; 2]) {
+        atlas.uv(self.sprite())
+    }
+
+    /// Returns true if a clip that plays [`Playback::Once`] got to the end
+    /// of its last frame.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advance the animation by the time elapsed since the previous frame of
+    /// a `canvas`, returning the events of the frames it got to.
+    pub fn advance<C: Canvas>(&mut self, canvas: &C) -> Vec<u32> {
+        self.advance_by(canvas.elapsed())
+    }
+
+    /// Advance the animation by `elapsed` time, returning the events of the
+    /// frames it got to, in order (including the first frame's, the first
+    /// time).
+    pub fn advance_by(&mut self, elapsed: Duration) -> Vec<u32> {
+        let mut events = Vec::new();
+        if self.starting {
+            self.starting = false;
+            self.events(&mut events);
+        }
+        if self.finished {
+            return events;
+        }
+        self.time += elapsed;
+        let last = self.clip.len() - 1;
+        while self.time >= self.clip.frames[self.frame].1 {
+            self.time -= self.clip.frames[self.frame].1;
+            self.frame = match self.clip.playback {
+                Playback::Once if self.frame == last => {
+                    self.finished = true;
+                    self.time = Duration::from_secs(0);
+                    break;
+                }
+                Playback::Loop if self.frame == last => 0,
+                Playback::Once | Playback::Loop => self.frame + 1,
+                Playback::PingPong => {
+                    if self.backwards && self.frame == 0
+                        || !self.backwards && self.frame == last
+                    {
+                        self.backwards = !self.backwards;
+                    }
+                    match (self.backwards, last) {
+                        (_, 0) => 0,
+                        (true, _) => self.frame - 1,
+                        (false, _) => self.frame + 1,
+                    }
+                }
+            };
+            self.events(&mut events);
+        }
+        events
+    }
+
+    // Add the events of the current frame.
+    fn events(&self, events: &mut Vec<u32>) {
+        let frame = self.frame;
+        events.extend(
+            self.clip
+                .events
+                .iter()
+                .filter(|(f, _event)| *f == frame)
+                .map(|(_frame, event)| *event),
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{Region, Texture};
+use pix::{rgb::SRgba8, Raster};
+
+// Empty pixels around each raster in the atlas, so they don't bleed together.
+const PADDING: u32 = 1;
+
+// Many rasters packed into one texture, using shelf packing.
+pub(super) struct Atlas {
+    raster: Raster<SRgba8>,
+    texture: Texture,
+    // Top-left of the next raster, and the current shelf height.
+    cursor: (u32, u32),
+    shelf: u32,
+}
+
+impl Atlas {
+    // Create an empty `size` by `size` atlas.
+    pub(super) fn new(size: u32) -> Self {
+        let raster = Raster::with_clear(size, size);
+        let texture = Texture::new(&raster);
+        Atlas {
+            raster,
+            texture,
+            cursor: (0, 0),
+            shelf: 0,
+        }
+    }
+
+    pub(super) fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    // Add a raster to the atlas, returning where it was put.
+    pub(super) fn add(&mut self, raster: &Raster<SRgba8>) -> Region {
+        let width = raster.width() + PADDING * 2;
+        let height = raster.height() + PADDING * 2;
+        let (x, y) = self.allocate(width, height);
+        let (x, y) = (x + PADDING, y + PADDING);
+        let region =
+            Region::new(x as i32, y as i32, raster.width(), raster.height());
+        self.texture.update(region, raster);
+        blit(&mut self.raster, x, y, raster);
+        region
+    }
+
+    // Get texture coordinates (offset and size) for a region of the atlas.
+    pub(super) fn coords(&self, region: Region) -> ([f32; 2], [f32; 2]) {
+        let (width, height) =
+            (self.raster.width() as f32, self.raster.height() as f32);
+        (
+            [region.left() as f32 / width, region.top() as f32 / height],
+            [
+                region.width() as f32 / width,
+                region.height() as f32 / height,
+            ],
+        )
+    }
+
+    // Find room for a raster, growing the atlas if it's full.
+    fn allocate(&mut self, width: u32, height: u32) -> (u32, u32) {
+        let mut size = (self.raster.width(), self.raster.height());
+        while width > size.0 {
+            size.0 *= 2;
+        }
+        if self.cursor.0 + width > size.0 {
+            self.cursor = (0, self.cursor.1 + self.shelf);
+            self.shelf = 0;
+        }
+        while self.cursor.1 + height > size.1 {
+            size.1 *= 2;
+        }
+        if size != (self.raster.width(), self.raster.height()) {
+            let mut raster = Raster::with_clear(size.0, size.1);
+            blit(&mut raster, 0, 0, &self.raster);
+            self.raster = raster;
+            self.texture = Texture::new(&self.raster);
+        }
+        let position = self.cursor;
+        self.cursor.0 += width;
+        self.shelf = self.shelf.max(height);
+        position
+    }
+}
+
+// Copy a raster into another at (x, y).
+fn blit(dst: &mut Raster<SRgba8>, x: u32, y: u32, src: &Raster<SRgba8>) {
+    let stride = dst.width() as usize * 4;
+    let len = src.width() as usize * 4;
+    if len == 0 {
+        return;
+    }
+    let pixels = dst.as_u8_slice_mut();
+    for (row, line) in src.as_u8_slice().chunks_exact(len).enumerate() {
+        let start = (y as usize + row) * stride + x as usize * 4;
+        pixels[start..start + len].copy_from_slice(line);
+    }
+}
@@ -0,0 +1,432 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::Transform;
+use std::f32::consts::PI;
+
+/// Something that positions what's drawn on a canvas, with
+/// [`Canvas::use_camera()`](super::Canvas::use_camera).
+///
+/// Cameras are given the canvas size every time they're used, so they follow
+/// resizes.
+pub trait Camera {
+    /// Get the transform for
+    /// [`Canvas::set_camera()`](super::Canvas::set_camera) on a canvas with
+    /// aspect ratio `height` (height / width) that's `pixel_width` pixels
+    /// wide.
+    fn transform(&self, height: f32, pixel_width: u32) -> Transform;
+
+    /// Get the ray from the camera through a `point` on the canvas (X from 0
+    /// to 1, Y from 0 to `height`), in world coordinates.
+    fn unproject(&self, height: f32, pixel_width: u32, point: [f32; 2]) -> Ray;
+}
+
+/// A ray in world coordinates, from [`Camera::unproject()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    /// Where the ray starts.
+    pub origin: [f32; 3],
+    /// Which way the ray points (length 1).
+    pub direction: [f32; 3],
+}
+
+impl Ray {
+    /// Get the point `distance` along the ray.
+    pub fn at(&self, distance: f32) -> [f32; 3] {
+        [
+            self.origin[0] + self.direction[0] * distance,
+            self.origin[1] + self.direction[1] * distance,
+            self.origin[2] + self.direction[2] * distance,
+        ]
+    }
+}
+
+/// A 3D camera at `position` that turns to look around, for shaders with
+/// depth.
+///
+/// The world has Y up, and the camera looks down -Z when it isn't turned.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, FirstPersonCamera, Group, Headless, Shader,
+///     ShaderBuilder, ShapeBuilder, Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(ShaderBuilder {
+///         depth: true,
+///         ..builder()
+///     });
+///     // A white square, 2 units wide, around the origin.
+///     #[rustfmt::skip]
+///     let square = ShapeBuilder::new()
+///         .vert(&[
+///             -1.0, -1.0, 0.0, 1.0, 1.0, 1.0,
+///              1.0, -1.0, 0.0, 1.0, 1.0, 1.0,
+///              1.0,  1.0, 0.0, 1.0, 1.0, 1.0,
+///             -1.0, -1.0, 0.0, 1.0, 1.0, 1.0,
+///              1.0,  1.0, 0.0, 1.0, 1.0, 1.0,
+///             -1.0,  1.0, 0.0, 1.0, 1.0, 1.0,
+///         ])
+///         .face(Transform::new())
+///         .finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &square, &Transform::new());
+///     // Seen from 2 units away, with a 90° field of view.
+///     let camera = FirstPersonCamera::new([0.0, 0.0, 2.0]);
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.use_camera(&camera);
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// let white = SRgba8::new(255, 255, 255, 255);
+/// assert_eq!(gpu.raster().pixel(2, 2), white);
+/// assert_eq!(gpu.raster().pixel(5, 5), white);
+/// assert_ne!(gpu.raster().pixel(1, 1), white);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FirstPersonCamera {
+    /// Where the camera is.
+    pub position: [f32; 3],
+    /// How far the camera is turned left, in cycles.
+    pub yaw: f32,
+    /// How far the camera is tilted up, in cycles (between -0.25 and 0.25).
+    pub pitch: f32,
+    /// Vertical field of view, in cycles.
+    pub fov: f32,
+}
+
+impl FirstPersonCamera {
+    /// Create a camera at `position` looking down -Z, with a 90° (0.25
+    /// cycle) field of view.
+    pub fn new(position: [f32; 3]) -> Self {
+        FirstPersonCamera {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 0.25,
+        }
+    }
+
+    /// Get the direction the camera is looking (length 1).
+    pub fn forward(&self) -> [f32; 3] {
+        Basis::new(self.yaw, self.pitch).forward
+    }
+
+    /// Get the direction to the camera's right (length 1, level with the
+    /// ground).
+    pub fn right(&self) -> [f32; 3] {
+        Basis::new(self.yaw, self.pitch).right
+    }
+}
+
+impl Camera for FirstPersonCamera {
+    fn transform(&self, height: f32, _pixel_width: u32) -> Transform {
+        let basis = Basis::new(self.yaw, self.pitch);
+        perspective(height, self.position, &basis, self.fov)
+    }
+
+    fn unproject(
+        &self,
+        height: f32,
+        _pixel_width: u32,
+        point: [f32; 2],
+    ) -> Ray {
+        let basis = Basis::new(self.yaw, self.pitch);
+        ray(height, self.position, &basis, self.fov, point)
+    }
+}
+
+/// A 3D camera that circles around a `target`, for shaders with depth.
+///
+/// The world has Y up, and the camera is on the +Z side of the target when
+/// it isn't turned.
+///
+/// ```rust
+/// use cala::graphics::{Camera, OrbitCamera};
+///
+/// let mut camera = OrbitCamera::new([0.0, 1.0, 0.0], 5.0);
+/// // Circle a quarter of the way around, to the +X side.
+/// camera.yaw = 0.25;
+/// let eye = camera.position();
+/// assert!((eye[0] - 5.0).abs() < 0.001 && eye[2].abs() < 0.001);
+/// // The center of the canvas is in front of the target.
+/// let ray = camera.unproject(0.5, 640, [0.5, 0.25]);
+/// let [x, y, z] = ray.at(5.0);
+/// assert!(x.abs() < 0.001 && (y - 1.0).abs() < 0.001 && z.abs() < 0.001);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OrbitCamera {
+    /// The point that the camera looks at.
+    pub target: [f32; 3],
+    /// How far the camera is from the target.
+    pub distance: f32,
+    /// How far the camera has circled around the target to the right, in
+    /// cycles.
+    pub yaw: f32,
+    /// How far the camera has circled above the target, in cycles (between
+    /// -0.25 and 0.25).
+    pub pitch: f32,
+    /// Vertical field of view, in cycles.
+    pub fov: f32,
+}
+
+impl OrbitCamera {
+    /// Create a camera `distance` away from `target`, with a 90° (0.25
+    /// cycle) field of view.
+    pub fn new(target: [f32; 3], distance: f32) -> Self {
+        OrbitCamera {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 0.25,
+        }
+    }
+
+    /// Get where the camera is.
+    pub fn position(&self) -> [f32; 3] {
+        let forward = self.basis().forward;
+        [
+            self.target[0] - forward[0] * self.distance,
+            self.target[1] - forward[1] * self.distance,
+            self.target[2] - forward[2] * self.distance,
+        ]
+    }
+
+    // Looking at the target, circling right is turning left.
+    fn basis(&self) -> Basis {
+        Basis::new(self.yaw, -self.pitch)
+    }
+}
+
+impl Camera for OrbitCamera {
+    fn transform(&self, height: f32, _pixel_width: u32) -> Transform {
+        perspective(height, self.position(), &self.basis(), self.fov)
+    }
+
+    fn unproject(
+        &self,
+        height: f32,
+        _pixel_width: u32,
+        point: [f32; 2],
+    ) -> Ray {
+        ray(height, self.position(), &self.basis(), self.fov, point)
+    }
+}
+
+/// A 2D camera measured in pixels, for shaders without depth.
+///
+/// World coordinates are pixels from the top left at `zoom` 1, and the
+/// camera's `position` is rounded so that whole world pixels land on whole
+/// canvas pixels.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Group, Headless, PixelCamera, Shader,
+///     ShapeBuilder, Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     // A 1 pixel white square.
+///     let pixel = ShapeBuilder::quad(&builder(), [1.0; 4]).finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &pixel, &Transform::new().translate(11.0, 11.0, 0.0));
+///     // Scrolled 10 pixels right and down, and zoomed in 2 times.
+///     let mut camera = PixelCamera::new();
+///     camera.position = [10.0, 10.0];
+///     camera.zoom = 2;
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.use_camera(&camera);
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// let white = SRgba8::new(255, 255, 255, 255);
+/// assert_eq!(gpu.raster().pixel(2, 2), white);
+/// assert_eq!(gpu.raster().pixel(3, 3), white);
+/// assert_ne!(gpu.raster().pixel(1, 1), white);
+/// assert_ne!(gpu.raster().pixel(4, 4), white);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelCamera {
+    /// The world pixel at the top left of the canvas.
+    pub position: [f32; 2],
+    /// How many canvas pixels wide each world pixel is.
+    pub zoom: u32,
+}
+
+impl Default for PixelCamera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PixelCamera {
+    /// Create a camera at the origin, with a zoom of 1.
+    pub fn new() -> Self {
+        PixelCamera {
+            position: [0.0, 0.0],
+            zoom: 1,
+        }
+    }
+
+    // Get the position rounded to canvas pixels, and the zoom.
+    fn snapped(&self) -> ([f32; 2], f32) {
+        let zoom = self.zoom.max(1) as f32;
+        let snap = |x: f32| (x * zoom).round() / zoom;
+        ([snap(self.position[0]), snap(self.position[1])], zoom)
+    }
+}
+
+impl Camera for PixelCamera {
+    fn transform(&self, height: f32, pixel_width: u32) -> Transform {
+        let ([x, y], zoom) = self.snapped();
+        let scale = zoom / pixel_width.max(1) as f32;
+        let view = Transform::from_mat4([
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-x * scale, -y * scale, 0.0, 1.0],
+        ]);
+        from_canvas(height) * view * to_canvas(height)
+    }
+
+    fn unproject(
+        &self,
+        _height: f32,
+        pixel_width: u32,
+        point: [f32; 2],
+    ) -> Ray {
+        let ([x, y], zoom) = self.snapped();
+        let pixels = pixel_width as f32 / zoom;
+        Ray {
+            origin: [point[0] * pixels + x, point[1] * pixels + y, 0.0],
+            direction: [0.0, 0.0, 1.0],
+        }
+    }
+}
+
+// Directions a 3D camera faces.
+#[derive(Copy, Clone)]
+struct Basis {
+    forward: [f32; 3],
+    right: [f32; 3],
+    up: [f32; 3],
+}
+
+impl Basis {
+    fn new(yaw: f32, pitch: f32) -> Self {
+        let (yaw, pitch) = (yaw * 2.0 * PI, pitch * 2.0 * PI);
+        let forward = [
+            -yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            -yaw.cos() * pitch.cos(),
+        ];
+        let right = [yaw.cos(), 0.0, -yaw.sin()];
+        let up = [
+            right[1] * forward[2] - right[2] * forward[1],
+            right[2] * forward[0] - right[0] * forward[2],
+            right[0] * forward[1] - right[1] * forward[0],
+        ];
+        Basis { forward, right, up }
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// The transform the GPU applies from canvas coordinates before the camera.
+fn to_canvas(height: f32) -> Transform {
+    Transform::new()
+        .scale(2.0, -2.0, -2.0)
+        .translate(-1.0, height, 0.0)
+}
+
+// Undo `to_canvas()`, so cameras can use their own world coordinates.
+fn from_canvas(height: f32) -> Transform {
+    Transform::from_mat4([
+        [0.5, 0.0, 0.0, 0.0],
+        [0.0, -0.5, 0.0, 0.0],
+        [0.0, 0.0, -0.5, 0.0],
+        [0.5, height * 0.5, 0.0, 1.0],
+    ])
+}
+
+// Get how much the view is scaled so the GPU's 90° horizontal perspective has
+// a vertical field of view of `fov` cycles.
+fn zoom(height: f32, fov: f32) -> f32 {
+    height / (fov * PI).tan()
+}
+
+fn perspective(
+    height: f32,
+    eye: [f32; 3],
+    basis: &Basis,
+    fov: f32,
+) -> Transform {
+    let s = zoom(height, fov);
+    let Basis { forward, right, up } = *basis;
+    let view = Transform::from_mat4([
+        [right[0] * s, up[0] * s, -forward[0], 0.0],
+        [right[1] * s, up[1] * s, -forward[1], 0.0],
+        [right[2] * s, up[2] * s, -forward[2], 0.0],
+        [
+            -dot(right, eye) * s,
+            -dot(up, eye) * s,
+            dot(forward, eye),
+            1.0,
+        ],
+    ]);
+    from_canvas(height) * view
+}
+
+fn ray(
+    height: f32,
+    eye: [f32; 3],
+    basis: &Basis,
+    fov: f32,
+    point: [f32; 2],
+) -> Ray {
+    let s = zoom(height, fov);
+    // Normalized device coordinates, then the view direction at depth 1.
+    let (x, y) = (point[0] * 2.0 - 1.0, 1.0 - point[1] * 2.0 / height);
+    let (x, y) = (x / s, y * height / s);
+    let Basis { forward, right, up } = *basis;
+    let mut direction = [0.0; 3];
+    for (i, d) in direction.iter_mut().enumerate() {
+        *d = right[i] * x + up[i] * y + forward[i];
+    }
+    let length = dot(direction, direction).sqrt();
+    for d in direction.iter_mut() {
+        *d /= length;
+    }
+    Ray {
+        origin: eye,
+        direction,
+    }
+}
@@ -0,0 +1,29 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Setup shared by the examples in the documentation, which run on a
+//! [`Headless`](super::Headless) GPU.  Not part of the public API.
+
+use super::ShaderBuilder;
+
+/// Settings for a shader with vertex colors and no depth (usually from
+/// `shader!()`).  It has no source, since the headless GPU doesn't compile
+/// shaders.  Change the settings with `ShaderBuilder { .., ..builder() }`.
+pub fn builder() -> ShaderBuilder {
+    ShaderBuilder {
+        tint: false,
+        gradient: true,
+        graphic: false,
+        depth: false,
+        blend: false,
+        opengl_frag: "\0",
+        opengl_vert: "\0",
+    }
+}
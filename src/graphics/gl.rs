@@ -0,0 +1,554 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! OpenGL(ES) and EGL calls that the `window` crate doesn't provide.  These use the
+//! same context as the `window` crate, so they must only be called from the
+//! draw thread.
+
+use super::{Blend, Cull, Pipeline, ShaderBuilder, ShaderStage};
+use pix::{rgb::SRgba8, Raster};
+use std::ffi::c_void;
+
+const GL_VIEWPORT: u32 = 0x0BA2;
+const GL_COLOR_CLEAR_VALUE: u32 = 0x0C22;
+const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
+const GL_DEPTH_BUFFER_BIT: u32 = 0x0100;
+const GL_FRAMEBUFFER: u32 = 0x8D40;
+const GL_RENDERBUFFER: u32 = 0x8D41;
+const GL_COLOR_ATTACHMENT0: u32 = 0x8CE0;
+const GL_DEPTH_ATTACHMENT: u32 = 0x8D00;
+const GL_DEPTH_COMPONENT16: u32 = 0x81A5;
+const GL_TEXTURE_2D: u32 = 0x0DE1;
+const GL_CW: u32 = 0x0900;
+const GL_CCW: u32 = 0x0901;
+const GL_TEXTURE_BINDING_2D: u32 = 0x8069;
+const GL_CURRENT_PROGRAM: u32 = 0x8B8D;
+const GL_RGBA: u32 = 0x1908;
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+const GL_BLEND: u32 = 0x0BE2;
+const GL_CULL_FACE: u32 = 0x0B44;
+const GL_FRONT: u32 = 0x0404;
+const GL_BACK: u32 = 0x0405;
+const GL_LESS: u32 = 0x0201;
+const GL_ALWAYS: u32 = 0x0207;
+const GL_ONE: u32 = 0x0001;
+const GL_SRC_ALPHA: u32 = 0x0302;
+const GL_ONE_MINUS_SRC_ALPHA: u32 = 0x0303;
+const GL_DST_ALPHA: u32 = 0x0304;
+const GL_FRAGMENT_SHADER: u32 = 0x8B30;
+const GL_VERTEX_SHADER: u32 = 0x8B31;
+const GL_COMPILE_STATUS: u32 = 0x8B81;
+const GL_LINK_STATUS: u32 = 0x8B82;
+const GL_INFO_LOG_LENGTH: u32 = 0x8B84;
+const GL_TEXTURE0: u32 = 0x84C0;
+const GL_TEXTURE1: u32 = 0x84C1;
+const GL_TEXTURE_MAG_FILTER: u32 = 0x2800;
+const GL_TEXTURE_MIN_FILTER: u32 = 0x2801;
+const GL_TEXTURE_WRAP_S: u32 = 0x2802;
+const GL_TEXTURE_WRAP_T: u32 = 0x2803;
+const GL_NEAREST: i32 = 0x2600;
+const GL_LINEAR: i32 = 0x2601;
+const GL_CLAMP_TO_EDGE: i32 = 0x812F;
+
+// Shaders that draw magenta, for each kind of vertex position and tint.
+const PLACEHOLDER_VERT_2D: &str = "uniform mat4 cam;
+attribute vec2 pos;
+void main() {
+    gl_Position = cam * vec4(pos, 0.0, 1.0);
+}\0";
+const PLACEHOLDER_VERT_3D: &str = "uniform mat4 cam;
+attribute vec3 pos;
+void main() {
+    gl_Position = cam * vec4(pos, 1.0);
+}\0";
+const PLACEHOLDER_FRAG: &str = "precision mediump float;
+void main() {
+    gl_FragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}\0";
+const PLACEHOLDER_FRAG_TINT: &str = "precision mediump float;
+uniform vec4 tint;
+void main() {
+    gl_FragColor = tint * vec4(1.0, 0.0, 1.0, 1.0);
+}\0";
+
+// Viewport and clear color of the window, while drawing into a texture.
+pub(super) type Saved = ([i32; 4], [f32; 4]);
+
+#[link(name = "GLESv2")]
+extern "C" {
+    fn glGetIntegerv(pname: u32, data: *mut i32);
+    fn glGetFloatv(pname: u32, data: *mut f32);
+    fn glViewport(x: i32, y: i32, width: i32, height: i32);
+    fn glClearColor(red: f32, green: f32, blue: f32, alpha: f32);
+    fn glClear(mask: u32);
+    fn glFrontFace(mode: u32);
+    fn glEnable(cap: u32);
+    fn glDisable(cap: u32);
+    fn glBlendFuncSeparate(src: u32, dst: u32, src_alpha: u32, dst_alpha: u32);
+    fn glDepthFunc(func: u32);
+    fn glDepthMask(flag: u8);
+    fn glCullFace(mode: u32);
+    fn glGenFramebuffers(n: i32, framebuffers: *mut u32);
+    fn glDeleteFramebuffers(n: i32, framebuffers: *const u32);
+    fn glBindFramebuffer(target: u32, framebuffer: u32);
+    fn glFramebufferTexture2D(
+        target: u32,
+        attachment: u32,
+        textarget: u32,
+        texture: u32,
+        level: i32,
+    );
+    fn glGenRenderbuffers(n: i32, renderbuffers: *mut u32);
+    fn glDeleteRenderbuffers(n: i32, renderbuffers: *const u32);
+    fn glBindRenderbuffer(target: u32, renderbuffer: u32);
+    fn glRenderbufferStorage(
+        target: u32,
+        internalformat: u32,
+        width: i32,
+        height: i32,
+    );
+    fn glFramebufferRenderbuffer(
+        target: u32,
+        attachment: u32,
+        renderbuffertarget: u32,
+        renderbuffer: u32,
+    );
+    fn glDeleteTextures(n: i32, textures: *const u32);
+    fn glDeleteProgram(program: u32);
+    fn glCreateShader(kind: u32) -> u32;
+    fn glDeleteShader(shader: u32);
+    fn glShaderSource(
+        shader: u32,
+        count: i32,
+        string: *const *const u8,
+        length: *const i32,
+    );
+    fn glCompileShader(shader: u32);
+    fn glGetShaderiv(shader: u32, pname: u32, params: *mut i32);
+    fn glGetShaderInfoLog(
+        shader: u32,
+        size: i32,
+        length: *mut i32,
+        log: *mut u8,
+    );
+    fn glCreateProgram() -> u32;
+    fn glAttachShader(program: u32, shader: u32);
+    fn glBindAttribLocation(program: u32, index: u32, name: *const u8);
+    fn glLinkProgram(program: u32);
+    fn glGetProgramiv(program: u32, pname: u32, params: *mut i32);
+    fn glGetProgramInfoLog(
+        program: u32,
+        size: i32,
+        length: *mut i32,
+        log: *mut u8,
+    );
+    fn glGetUniformLocation(program: u32, name: *const u8) -> i32;
+    fn glUseProgram(program: u32);
+    fn glUniform1i(location: i32, v0: i32);
+    fn glUniform2f(location: i32, v0: f32, v1: f32);
+    fn glUniform4f(location: i32, v0: f32, v1: f32, v2: f32, v3: f32);
+    fn glActiveTexture(texture: u32);
+    fn glBindTexture(target: u32, texture: u32);
+    fn glTexParameteri(target: u32, pname: u32, param: i32);
+    fn glCopyTexSubImage2D(
+        target: u32,
+        level: i32,
+        xoffset: i32,
+        yoffset: i32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    );
+    fn glReadPixels(
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        type_: u32,
+        pixels: *mut c_void,
+    );
+}
+
+#[link(name = "EGL")]
+extern "C" {
+    fn eglGetCurrentDisplay() -> *mut c_void;
+    fn eglSwapInterval(display: *mut c_void, interval: i32) -> u32;
+}
+
+// Turn vsync on or off.
+pub(super) fn swap_interval(vsync: bool) {
+    unsafe { eglSwapInterval(eglGetCurrentDisplay(), vsync as i32) };
+}
+
+// Get the viewport (x, y, width, height).
+pub(super) fn viewport() -> [i32; 4] {
+    let mut viewport = [0; 4];
+    unsafe { glGetIntegerv(GL_VIEWPORT, viewport.as_mut_ptr()) };
+    viewport
+}
+
+// Get the width of the viewport in pixels.
+pub(super) fn width() -> u32 {
+    viewport()[2] as u32
+}
+
+// Get the name of the currently bound 2D texture.
+pub(super) fn texture_binding() -> u32 {
+    let mut texture = 0;
+    unsafe { glGetIntegerv(GL_TEXTURE_BINDING_2D, &mut texture) };
+    texture as u32
+}
+
+// Get the name of the shader program that's in use.
+pub(super) fn current_program() -> u32 {
+    let mut program = 0;
+    unsafe { glGetIntegerv(GL_CURRENT_PROGRAM, &mut program) };
+    program as u32
+}
+
+// Delete a texture (not used by the `window` crate after being deleted).
+pub(super) fn delete_texture(texture: u32) {
+    unsafe { glDeleteTextures(1, &texture) };
+}
+
+// Delete a shader program (not used by the `window` crate after being
+// deleted).
+pub(super) fn delete_program(program: u32) {
+    unsafe { glDeleteProgram(program) };
+}
+
+// Set blending, depth testing and culling for the next draw.  The `window`
+// crate never changes these as long as its shaders don't blend, and enables
+// depth testing for shaders with depth.
+pub(super) fn pipeline(pipeline: &Pipeline) {
+    unsafe {
+        match pipeline.blend {
+            Blend::Opaque => glDisable(GL_BLEND),
+            Blend::Alpha => {
+                glEnable(GL_BLEND);
+                glBlendFuncSeparate(
+                    GL_SRC_ALPHA,
+                    GL_ONE_MINUS_SRC_ALPHA,
+                    GL_SRC_ALPHA,
+                    GL_DST_ALPHA,
+                );
+            }
+            Blend::Additive => {
+                glEnable(GL_BLEND);
+                glBlendFuncSeparate(
+                    GL_SRC_ALPHA,
+                    GL_ONE,
+                    GL_SRC_ALPHA,
+                    GL_DST_ALPHA,
+                );
+            }
+        }
+        glDepthFunc(if pipeline.depth_test {
+            GL_LESS
+        } else {
+            GL_ALWAYS
+        });
+        glDepthMask(pipeline.depth_write as u8);
+        match pipeline.cull {
+            Cull::None => glDisable(GL_CULL_FACE),
+            Cull::Back => {
+                glEnable(GL_CULL_FACE);
+                glCullFace(GL_BACK);
+            }
+            Cull::Front => {
+                glEnable(GL_CULL_FACE);
+                glCullFace(GL_FRONT);
+            }
+        }
+    }
+}
+
+// Turn depth writes back on after a draw, so the depth buffer gets cleared.
+pub(super) fn depth_write() {
+    unsafe { glDepthMask(1) };
+}
+
+// Get a shader with the same settings as `builder` that draws magenta.
+pub(super) fn placeholder(builder: &ShaderBuilder) -> ShaderBuilder {
+    ShaderBuilder {
+        tint: builder.tint,
+        gradient: builder.gradient,
+        graphic: builder.graphic,
+        depth: builder.depth,
+        blend: builder.blend,
+        opengl_frag: if builder.tint {
+            PLACEHOLDER_FRAG_TINT
+        } else {
+            PLACEHOLDER_FRAG
+        },
+        opengl_vert: if builder.depth {
+            PLACEHOLDER_VERT_3D
+        } else {
+            PLACEHOLDER_VERT_2D
+        },
+    }
+}
+
+// Compile and link a shader the same way as the `window` crate (which panics
+// on errors), returning the error instead.
+pub(super) fn compile(
+    builder: &ShaderBuilder,
+) -> Result<(), (ShaderStage, String)> {
+    unsafe {
+        let vert = glCreateShader(GL_VERTEX_SHADER);
+        let frag = glCreateShader(GL_FRAGMENT_SHADER);
+        let program = glCreateProgram();
+        let result = (|| {
+            compile_shader(vert, builder.opengl_vert)
+                .map_err(|log| (ShaderStage::Vertex, log))?;
+            compile_shader(frag, builder.opengl_frag)
+                .map_err(|log| (ShaderStage::Fragment, log))?;
+            glAttachShader(program, vert);
+            glAttachShader(program, frag);
+            // Same attribute locations as the `window` crate.
+            glBindAttribLocation(program, 0, b"pos\0".as_ptr());
+            glBindAttribLocation(program, 2, b"col\0".as_ptr());
+            glBindAttribLocation(program, 1, b"texpos\0".as_ptr());
+            glLinkProgram(program);
+            let mut status = 0;
+            glGetProgramiv(program, GL_LINK_STATUS, &mut status);
+            if status == 0 {
+                let mut len = 0;
+                glGetProgramiv(program, GL_INFO_LOG_LENGTH, &mut len);
+                let mut log = vec![0; len.max(1) as usize];
+                glGetProgramInfoLog(
+                    program,
+                    log.len() as i32,
+                    &mut len,
+                    log.as_mut_ptr(),
+                );
+                return Err((ShaderStage::Link, info_log(log, len)));
+            }
+            // The `window` crate also needs these uniforms.
+            let mut uniforms = vec!["cam"];
+            if builder.tint {
+                uniforms.push("tint");
+            }
+            for uniform in uniforms {
+                let name = format!("{}\0", uniform);
+                if glGetUniformLocation(program, name.as_ptr()) < 0 {
+                    let log = format!("Missing uniform `{}`", uniform);
+                    return Err((ShaderStage::Link, log));
+                }
+            }
+            Ok(())
+        })();
+        glDeleteProgram(program);
+        glDeleteShader(vert);
+        glDeleteShader(frag);
+        result
+    }
+}
+
+// Compile one stage of a shader, returning the info log if it fails.
+unsafe fn compile_shader(shader: u32, source: &str) -> Result<(), String> {
+    glShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+    glCompileShader(shader);
+    let mut status = 0;
+    glGetShaderiv(shader, GL_COMPILE_STATUS, &mut status);
+    if status == 0 {
+        let mut len = 0;
+        glGetShaderiv(shader, GL_INFO_LOG_LENGTH, &mut len);
+        let mut log = vec![0; len.max(1) as usize];
+        glGetShaderInfoLog(
+            shader,
+            log.len() as i32,
+            &mut len,
+            log.as_mut_ptr(),
+        );
+        return Err(info_log(log, len));
+    }
+    Ok(())
+}
+
+// Convert an info log of `len` bytes to a `String`.
+fn info_log(mut log: Vec<u8>, len: i32) -> String {
+    log.truncate(len.max(0) as usize);
+    String::from_utf8_lossy(&log).into_owned()
+}
+
+// Read back what has been drawn so far this frame.
+pub(super) fn read_pixels() -> Raster<SRgba8> {
+    let [x, y, width, height] = viewport();
+    let mut raster = Raster::with_clear(width as u32, height as u32);
+    unsafe {
+        glReadPixels(
+            x,
+            y,
+            width,
+            height,
+            GL_RGBA,
+            GL_UNSIGNED_BYTE,
+            raster.as_u8_slice_mut().as_mut_ptr().cast(),
+        );
+    }
+    // OpenGL rows go from bottom to top.
+    let stride = width as usize * 4;
+    let pixels = raster.as_u8_slice_mut();
+    for row in 0..height as usize / 2 {
+        let (top, bottom) =
+            pixels.split_at_mut((height as usize - row - 1) * stride);
+        top[row * stride..(row + 1) * stride]
+            .swap_with_slice(&mut bottom[..stride]);
+    }
+    raster
+}
+
+// Set the bound texture to be sampled with `filter`, clamped to its edges
+// (which also lets sizes that aren't a power of two be sampled in GLES2).
+fn clamp_texture(filter: i32) {
+    unsafe {
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, filter);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, filter);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE);
+    }
+}
+
+// Set the bound texture to be sampled with linear filtering, for effects.
+pub(super) fn smooth_texture() {
+    clamp_texture(GL_LINEAR);
+}
+
+// Copy what has been drawn on the window so far into a texture the size of
+// the viewport, leaving it bound.
+pub(super) fn copy_screen(texture: u32) {
+    let [x, y, width, height] = viewport();
+    unsafe {
+        glBindTexture(GL_TEXTURE_2D, texture);
+        glCopyTexSubImage2D(GL_TEXTURE_2D, 0, 0, 0, x, y, width, height);
+    }
+}
+
+// Bind a color lookup table to texture unit 1, with nearest filtering.
+pub(super) fn bind_lut(texture: u32) {
+    unsafe {
+        glActiveTexture(GL_TEXTURE1);
+        glBindTexture(GL_TEXTURE_2D, texture);
+        clamp_texture(GL_NEAREST);
+        glActiveTexture(GL_TEXTURE0);
+    }
+}
+
+// Set the uniforms of an effect shader, which stay set for its program: its
+// settings, the size of the screen in pixels and the lookup table's texture
+// unit.  Unused uniforms have no location, so setting them does nothing.
+pub(super) fn effect_uniforms(program: u32, params: [f32; 4], size: [f32; 2]) {
+    unsafe {
+        glUseProgram(program);
+        let [a, b, c, d] = params;
+        glUniform4f(
+            glGetUniformLocation(program, b"params\0".as_ptr()),
+            a,
+            b,
+            c,
+            d,
+        );
+        glUniform2f(
+            glGetUniformLocation(program, b"size\0".as_ptr()),
+            size[0],
+            size[1],
+        );
+        glUniform1i(glGetUniformLocation(program, b"lut\0".as_ptr()), 1);
+    }
+}
+
+// A framebuffer that draws into a texture, with its own depth buffer.
+pub(super) struct Framebuffer {
+    framebuffer: u32,
+    depth: u32,
+    width: i32,
+    height: i32,
+}
+
+impl Framebuffer {
+    // Create a framebuffer for a `width` by `height` texture.
+    pub(super) fn new(texture: u32, width: u32, height: u32) -> Self {
+        let (width, height) = (width as i32, height as i32);
+        let mut framebuffer = 0;
+        let mut depth = 0;
+        unsafe {
+            glGenRenderbuffers(1, &mut depth);
+            glBindRenderbuffer(GL_RENDERBUFFER, depth);
+            glRenderbufferStorage(
+                GL_RENDERBUFFER,
+                GL_DEPTH_COMPONENT16,
+                width,
+                height,
+            );
+            glGenFramebuffers(1, &mut framebuffer);
+            glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+            glFramebufferTexture2D(
+                GL_FRAMEBUFFER,
+                GL_COLOR_ATTACHMENT0,
+                GL_TEXTURE_2D,
+                texture,
+                0,
+            );
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_DEPTH_ATTACHMENT,
+                GL_RENDERBUFFER,
+                depth,
+            );
+            glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        }
+        Framebuffer {
+            framebuffer,
+            depth,
+            width,
+            height,
+        }
+    }
+
+    // Start drawing into the texture, cleared to `color`.  Returns the
+    // viewport and clear color to restore with `unbind()`.
+    pub(super) fn bind(&self, color: [f32; 4]) -> Saved {
+        let viewport = viewport();
+        let mut clear = [0.0; 4];
+        unsafe {
+            glGetFloatv(GL_COLOR_CLEAR_VALUE, clear.as_mut_ptr());
+            glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffer);
+            glViewport(0, 0, self.width, self.height);
+            // Drawing is flipped upside down, which also flips the winding.
+            glFrontFace(GL_CW);
+            glClearColor(color[0], color[1], color[2], color[3]);
+            glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        }
+        (viewport, clear)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            glDeleteFramebuffers(1, &self.framebuffer);
+            glDeleteRenderbuffers(1, &self.depth);
+        }
+    }
+}
+
+// Go back to drawing on the window.
+pub(super) fn unbind(saved: Saved) {
+    let ([x, y, width, height], [r, g, b, a]) = saved;
+    unsafe {
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        glViewport(x, y, width, height);
+        glFrontFace(GL_CCW);
+        glClearColor(r, g, b, a);
+    }
+}
@@ -0,0 +1,754 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::mesh::{texture, Mesh, MeshError};
+use std::path::Path;
+
+// Column-major 4x4 matrix.
+type Mat4 = [[f32; 4]; 4];
+
+const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn invalid(what: &str) -> MeshError {
+    MeshError::Invalid(format!("glTF: {}", what))
+}
+
+fn unsupported(feature: &str) -> MeshError {
+    MeshError::Unsupported(format!("{} in glTF files", feature))
+}
+
+// A parsed JSON value.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    // Get the value of a key in an object.
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match *self {
+            Json::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    fn index(&self) -> Option<usize> {
+        self.number()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    fn string(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    // Get an array of numbers.
+    fn numbers(&self) -> Vec<f32> {
+        self.array()
+            .iter()
+            .filter_map(Json::number)
+            .map(|n| n as f32)
+            .collect()
+    }
+}
+
+// Get the item at `index` of a top-level array, like `"accessors"`.
+fn item<'a>(
+    root: &'a Json,
+    list: &str,
+    index: usize,
+) -> Result<&'a Json, MeshError> {
+    root.get(list)
+        .map(Json::array)
+        .and_then(|items| items.get(index))
+        .ok_or_else(|| invalid(&format!("missing {} {}", list, index)))
+}
+
+// Get a required index property.
+fn required(json: &Json, key: &str) -> Result<usize, MeshError> {
+    json.get(key)
+        .and_then(Json::index)
+        .ok_or_else(|| invalid(&format!("missing `{}`", key)))
+}
+
+// How deeply JSON arrays and objects may nest, so that parsing can't overflow
+// the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    // Arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.at) {
+            self.at += 1;
+        }
+    }
+
+    // Consume `byte` (after whitespace) if it's next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.at) == Some(&byte) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), MeshError> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(invalid(&format!("expected `{}` in JSON", byte as char)))
+        }
+    }
+
+    // Enter an array or object.
+    fn nest(&mut self) -> Result<(), MeshError> {
+        self.at += 1;
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("JSON nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, MeshError> {
+        self.skip_whitespace();
+        let rest = &self.bytes[self.at..];
+        for (word, value) in [
+            (&b"null"[..], Json::Null),
+            (&b"true"[..], Json::Bool(true)),
+            (&b"false"[..], Json::Bool(false)),
+        ] {
+            if rest.starts_with(word) {
+                self.at += word.len();
+                return Ok(value);
+            }
+        }
+        match rest.first() {
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => {
+                self.nest()?;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.nest()?;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Object(members))
+            }
+            _ => {
+                let len = rest
+                    .iter()
+                    .take_while(|b| b"+-0123456789.eE".contains(b))
+                    .count();
+                self.at += len;
+                std::str::from_utf8(&rest[..len])
+                    .ok()
+                    .and_then(|number| number.parse().ok())
+                    .map(Json::Number)
+                    .ok_or_else(|| invalid("expected a value in JSON"))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, MeshError> {
+        if self.bytes.get(self.at) != Some(&b'"') {
+            return Err(invalid("expected a string in JSON"));
+        }
+        self.at += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self
+                .bytes
+                .get(self.at)
+                .ok_or_else(|| invalid("unterminated string in JSON"))?;
+            self.at += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.bytes.get(self.at).copied();
+                    self.at += 1;
+                    let c = match escape {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'u') => self.unicode()?,
+                        Some(c @ (b'"' | b'\\' | b'/')) => c as char,
+                        _ => return Err(invalid("invalid escape in JSON")),
+                    };
+                    let mut utf8 = [0; 4];
+                    bytes
+                        .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("JSON isn't UTF-8"))
+    }
+
+    // Parse the 4 hex digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u32, MeshError> {
+        let digits = self.bytes.get(self.at..self.at + 4);
+        self.at += 4;
+        digits
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| invalid("invalid escape in JSON"))
+    }
+
+    // Parse the rest of a `\u` escape, including surrogate pairs.
+    fn unicode(&mut self) -> Result<char, MeshError> {
+        let high = self.hex()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.bytes.get(self.at..self.at + 2) != Some(b"\\u") {
+                return Err(invalid("invalid escape in JSON"));
+            }
+            self.at += 2;
+            let low = self.hex()?;
+            0x10000
+                + ((high - 0xD800) << 10)
+                + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        std::char::from_u32(code)
+            .ok_or_else(|| invalid("invalid escape in JSON"))
+    }
+}
+
+// Decode base64 (standard alphabet, with optional padding).
+fn base64(text: &str) -> Result<Vec<u8>, MeshError> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid("invalid base64 data")),
+        };
+        bits = bits << 6 | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// Get the data of a `data:` URI.
+fn data_uri(uri: &str, what: &str) -> Result<Vec<u8>, MeshError> {
+    if !uri.starts_with("data:") {
+        return Err(unsupported(&format!("external {}", what)));
+    }
+    match uri.find(";base64,") {
+        Some(start) => base64(&uri[start + 8..]),
+        None => Err(invalid("data URI isn't base64")),
+    }
+}
+
+// Split a binary glTF file into its JSON and binary chunks.
+fn glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), MeshError> {
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("truncated binary file"))
+    };
+    if u32_at(4)? != 2 {
+        return Err(unsupported("versions other than 2.0"));
+    }
+    let (mut json, mut bin) = (None, None);
+    let mut at = 12;
+    while at < bytes.len().min(u32_at(8)?) {
+        let (len, kind) = (u32_at(at)?, u32_at(at + 4)?);
+        let chunk = bytes
+            .get(at + 8..at + 8 + len)
+            .ok_or_else(|| invalid("truncated binary file"))?;
+        match kind {
+            0x4E4F_534A if json.is_none() => json = Some(chunk),
+            0x004E_4942 if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        at += 8 + len;
+    }
+    Ok((json.ok_or_else(|| invalid("missing JSON chunk"))?, bin))
+}
+
+// A glTF file, with its buffers loaded.
+struct Gltf {
+    root: Json,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Gltf {
+    // Get the bytes of a buffer view.
+    fn view(&self, index: usize) -> Result<(&[u8], Option<usize>), MeshError> {
+        let view = item(&self.root, "bufferViews", index)?;
+        let buffer = self
+            .buffers
+            .get(required(view, "buffer")?)
+            .ok_or_else(|| invalid("missing buffer"))?;
+        let offset = view.get("byteOffset").and_then(Json::index).unwrap_or(0);
+        let len = required(view, "byteLength")?;
+        let stride = view.get("byteStride").and_then(Json::index);
+        let bytes = offset
+            .checked_add(len)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid("buffer view out of bounds"))?;
+        Ok((bytes, stride))
+    }
+
+    // Read an accessor as `(values, components)`, with integers that are
+    // `normalized` (or colors and texture coordinates) converted to 0 to 1.
+    fn accessor(
+        &self,
+        index: usize,
+        normalized: bool,
+    ) -> Result<(Vec<f64>, usize), MeshError> {
+        let accessor = item(&self.root, "accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(unsupported("sparse accessors"));
+        }
+        let components = match accessor.get("type").and_then(Json::string) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(unsupported("matrix accessors")),
+        };
+        let kind = required(accessor, "componentType")?;
+        let size = match kind {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(invalid("unknown component type")),
+        };
+        let normalized = normalized
+            || matches!(accessor.get("normalized"), Some(Json::Bool(true)));
+        let count = required(accessor, "count")?;
+        let out_of_bounds = || invalid("accessor out of bounds");
+        let len = count.checked_mul(components).ok_or_else(out_of_bounds)?;
+        let view = match accessor.get("bufferView").and_then(Json::index) {
+            Some(view) => view,
+            None => {
+                let mut values = Vec::new();
+                values.try_reserve_exact(len).map_err(|_| out_of_bounds())?;
+                values.resize(len, 0.0);
+                return Ok((values, components));
+            }
+        };
+        let (bytes, stride) = self.view(view)?;
+        let offset = accessor.get("byteOffset").and_then(Json::index);
+        let offset = offset.unwrap_or(0);
+        let stride = stride.unwrap_or(size * components);
+        if stride < size * components {
+            return Err(invalid("byte stride too small"));
+        }
+        // Check that the last element fits before allocating, so the
+        // values are bounded by the length of the buffer view.
+        if count > 0 {
+            let end = (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(offset))
+                .and_then(|last| last.checked_add(size * components))
+                .ok_or_else(out_of_bounds)?;
+            if end > bytes.len() {
+                return Err(out_of_bounds());
+            }
+        }
+        let mut values = Vec::with_capacity(len);
+        for i in 0..count {
+            for c in 0..components {
+                let at = offset + i * stride + c * size;
+                let b = &bytes[at..at + size];
+                let (value, max) = match kind {
+                    5120 => (f64::from(b[0] as i8), 127.0),
+                    5121 => (f64::from(b[0]), 255.0),
+                    5122 => {
+                        (f64::from(i16::from_le_bytes([b[0], b[1]])), 32767.0)
+                    }
+                    5123 => {
+                        (f64::from(u16::from_le_bytes([b[0], b[1]])), 65535.0)
+                    }
+                    5125 => {
+                        let value =
+                            u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                        (f64::from(value), f64::from(u32::MAX))
+                    }
+                    _ => {
+                        let value =
+                            f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                        (f64::from(value), 1.0)
+                    }
+                };
+                values.push(if normalized {
+                    (value / max).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok((values, components))
+    }
+
+    // Load an image.
+    fn image(&self, index: usize) -> Result<Vec<u8>, MeshError> {
+        let image = item(&self.root, "images", index)?;
+        if let Some(uri) = image.get("uri").and_then(Json::string) {
+            return data_uri(uri, "images");
+        }
+        Ok(self.view(required(image, "bufferView")?)?.0.to_vec())
+    }
+
+    // Load the meshes of a node and its children.
+    fn node(
+        &self,
+        index: usize,
+        parent: &Mat4,
+        meshes: &mut Vec<Mesh>,
+        depth: usize,
+    ) -> Result<(), MeshError> {
+        if depth > self.root.get("nodes").map_or(0, |n| n.array().len()) {
+            return Err(invalid("node hierarchy has a cycle"));
+        }
+        let node = item(&self.root, "nodes", index)?;
+        let transform = multiply(parent, &local(node));
+        if let Some(mesh) = node.get("mesh").and_then(Json::index) {
+            self.mesh(mesh, &transform, meshes)?;
+        }
+        if let Some(children) = node.get("children") {
+            for child in children.array().iter().filter_map(Json::index) {
+                self.node(child, &transform, meshes, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Load the primitives of a mesh.
+    fn mesh(
+        &self,
+        index: usize,
+        transform: &Mat4,
+        meshes: &mut Vec<Mesh>,
+    ) -> Result<(), MeshError> {
+        let mesh = item(&self.root, "meshes", index)?;
+        for primitive in mesh.get("primitives").map_or(&[][..], Json::array) {
+            meshes.push(self.primitive(primitive, transform)?);
+        }
+        Ok(())
+    }
+
+    fn primitive(
+        &self,
+        primitive: &Json,
+        transform: &Mat4,
+    ) -> Result<Mesh, MeshError> {
+        let mode = primitive.get("mode").and_then(Json::index).unwrap_or(4);
+        if mode != 4 {
+            return Err(unsupported("primitives that aren't triangle lists"));
+        }
+        let attributes = primitive
+            .get("attributes")
+            .ok_or_else(|| invalid("missing `attributes`"))?;
+        let (positions, components) =
+            self.accessor(required(attributes, "POSITION")?, false)?;
+        if components != 3 {
+            return Err(invalid("positions aren't VEC3"));
+        }
+        let count = positions.len() / 3;
+
+        // Base color of the material.
+        let mut factor = [1.0; 4];
+        let mut texture_info = None;
+        if let Some(material) = primitive.get("material").and_then(Json::index)
+        {
+            let material = item(&self.root, "materials", material)?;
+            if let Some(pbr) = material.get("pbrMetallicRoughness") {
+                if let Some(color) = pbr.get("baseColorFactor") {
+                    let color = color.numbers();
+                    if color.len() == 4 {
+                        factor.copy_from_slice(&color);
+                    }
+                }
+                texture_info = pbr.get("baseColorTexture");
+            }
+        }
+        let mut image = None;
+        let mut texcoord = 0;
+        if let Some(info) = texture_info {
+            let index = required(info, "index")?;
+            let source =
+                required(item(&self.root, "textures", index)?, "source")?;
+            image = Some(texture(self.image(source)?)?);
+            texcoord = info.get("texCoord").and_then(Json::index).unwrap_or(0);
+        }
+
+        let texcoords = match attributes
+            .get(&format!("TEXCOORD_{}", texcoord))
+            .and_then(Json::index)
+        {
+            Some(accessor) => self.accessor(accessor, true)?.0,
+            None => vec![0.0; count * 2],
+        };
+        let (colors, color_components) =
+            match attributes.get("COLOR_0").and_then(Json::index) {
+                Some(accessor) => self.accessor(accessor, true)?,
+                None => (vec![1.0; count * 4], 4),
+            };
+        if texcoords.len() != count * 2
+            || colors.len() != count * color_components
+        {
+            return Err(invalid("attributes have different counts"));
+        }
+
+        let indices = match primitive.get("indices").and_then(Json::index) {
+            Some(accessor) => self
+                .accessor(accessor, false)?
+                .0
+                .into_iter()
+                .map(|index| index as usize)
+                .collect(),
+            None => (0..count).collect::<Vec<_>>(),
+        };
+        let mut mesh = Mesh {
+            positions: Vec::with_capacity(indices.len()),
+            texcoords: Vec::with_capacity(indices.len()),
+            colors: Vec::with_capacity(indices.len()),
+            texture: image,
+        };
+        for triangle in indices.chunks_exact(3) {
+            for &i in triangle {
+                if i >= count {
+                    return Err(invalid("index out of range"));
+                }
+                let p = &positions[i * 3..i * 3 + 3];
+                mesh.positions.push(apply(
+                    transform,
+                    [p[0] as f32, p[1] as f32, p[2] as f32],
+                ));
+                mesh.texcoords.push([
+                    texcoords[i * 2] as f32,
+                    texcoords[i * 2 + 1] as f32,
+                ]);
+                let mut color = [1.0; 4];
+                let c = &colors[i * color_components..][..color_components];
+                for (channel, c) in color.iter_mut().zip(c) {
+                    *channel = *c as f32;
+                }
+                for (channel, factor) in color.iter_mut().zip(factor.iter()) {
+                    *channel *= factor;
+                }
+                mesh.colors.push(color);
+            }
+        }
+        Ok(mesh)
+    }
+}
+
+// Get the transform of a node relative to its parent.
+fn local(node: &Json) -> Mat4 {
+    if let Some(matrix) = node.get("matrix") {
+        let m = matrix.numbers();
+        if m.len() == 16 {
+            let mut matrix = IDENTITY;
+            for (i, value) in m.into_iter().enumerate() {
+                matrix[i / 4][i % 4] = value;
+            }
+            return matrix;
+        }
+    }
+    let get = |key: &str, default: &[f32]| {
+        let values = node.get(key).map(Json::numbers).unwrap_or_default();
+        if values.len() == default.len() {
+            values
+        } else {
+            default.to_vec()
+        }
+    };
+    let t = get("translation", &[0.0, 0.0, 0.0]);
+    let [x, y, z, w] = match get("rotation", &[0.0, 0.0, 0.0, 1.0])[..] {
+        [x, y, z, w] => [x, y, z, w],
+        _ => [0.0, 0.0, 0.0, 1.0],
+    };
+    let s = get("scale", &[1.0, 1.0, 1.0]);
+    // Translation × rotation (from the quaternion) × scale.
+    [
+        [
+            (1.0 - 2.0 * (y * y + z * z)) * s[0],
+            2.0 * (x * y + z * w) * s[0],
+            2.0 * (x * z - y * w) * s[0],
+            0.0,
+        ],
+        [
+            2.0 * (x * y - z * w) * s[1],
+            (1.0 - 2.0 * (x * x + z * z)) * s[1],
+            2.0 * (y * z + x * w) * s[1],
+            0.0,
+        ],
+        [
+            2.0 * (x * z + y * w) * s[2],
+            2.0 * (y * z - x * w) * s[2],
+            (1.0 - 2.0 * (x * x + y * y)) * s[2],
+            0.0,
+        ],
+        [t[0], t[1], t[2], 1.0],
+    ]
+}
+
+fn multiply(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (column, b) in out.iter_mut().zip(b.iter()) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[k]).sum();
+        }
+    }
+    out
+}
+
+fn apply(m: &Mat4, p: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (row, value) in out.iter_mut().enumerate() {
+        *value =
+            m[0][row] * p[0] + m[1][row] * p[1] + m[2][row] * p[2] + m[3][row];
+    }
+    out
+}
+
+pub(super) fn load(path: &Path) -> Result<Vec<Mesh>, MeshError> {
+    let bytes = std::fs::read(path)?;
+    let (json, bin) = if bytes.starts_with(b"glTF") {
+        glb(&bytes)?
+    } else {
+        (&bytes[..], None)
+    };
+    let mut parser = Parser {
+        bytes: json,
+        at: 0,
+        depth: 0,
+    };
+    let root = parser.value()?;
+    parser.skip_whitespace();
+    if parser.at != json.len() {
+        return Err(invalid("trailing characters after JSON"));
+    }
+
+    let version = root
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Json::string)
+        .ok_or_else(|| invalid("missing asset version"))?;
+    if !version.starts_with("2.") {
+        return Err(unsupported(&format!("version {}", version)));
+    }
+    if let Some(extension) = root
+        .get("extensionsRequired")
+        .and_then(|required| required.array().first())
+        .and_then(Json::string)
+    {
+        return Err(unsupported(&format!("extension {}", extension)));
+    }
+
+    let mut buffers = Vec::new();
+    for buffer in root.get("buffers").map_or(&[][..], Json::array) {
+        buffers.push(match buffer.get("uri").and_then(Json::string) {
+            Some(uri) => data_uri(uri, "buffers")?,
+            None => {
+                bin.ok_or_else(|| invalid("missing binary chunk"))?.to_vec()
+            }
+        });
+    }
+    let gltf = Gltf { root, buffers };
+
+    let mut meshes = Vec::new();
+    let scene = gltf.root.get("scene").and_then(Json::index).unwrap_or(0);
+    match gltf.root.get("scenes").map(Json::array) {
+        Some(scenes) if !scenes.is_empty() => {
+            let scene = item(&gltf.root, "scenes", scene)?;
+            for node in scene.get("nodes").map_or(&[][..], Json::array) {
+                let node = node.index().ok_or_else(|| invalid("bad node"))?;
+                gltf.node(node, &IDENTITY, &mut meshes, 0)?;
+            }
+        }
+        // Without scenes, load every mesh untransformed.
+        _ => {
+            let count = gltf.root.get("meshes").map_or(0, |m| m.array().len());
+            for mesh in 0..count {
+                gltf.mesh(mesh, &IDENTITY, &mut meshes)?;
+            }
+        }
+    }
+    Ok(meshes)
+}
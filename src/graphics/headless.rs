@@ -0,0 +1,719 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    async_runner, blit, captured, mat4, post, registry, Backend, Blend, Cull,
+    GpuCmd, Id, Pipeline, Player, Resource, ResourceError, ShapeBuilder, Slots,
+    Transform,
+};
+use footile::{FillRule, Path2D, Plotter};
+use pix::{matte::Matte8, rgb::SRgba8, Raster};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Same clipping planes as the `window` crate.
+const NEAR: f32 = 0.01;
+const HORIZON: f32 = 5000.0;
+
+// Whether a `Headless` is taking frames from the async thread.
+static LIVE: AtomicBool = AtomicBool::new(false);
+
+// A shader program for the CPU.
+struct Program {
+    tint: Option<[f32; 4]>,
+    gradient: bool,
+    graphic: bool,
+    depth: bool,
+    blend: bool,
+}
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    pos: [f32; 3],
+    col: [f32; 4],
+    tex: [f32; 2],
+}
+
+/// A GPU emulated on the CPU, for running without a window.
+///
+/// Commands from [`Frame`](crate::window::Frame)s are rasterized with
+/// [`footile`](https://crates.io/crates/footile) into a `Raster`, so
+/// application code can run unchanged on machines without a GPU (like CI).
+///
+/// Every `Headless` in a process takes frames from the same async thread, so
+/// only one of them can [`run()`](Headless::run) at a time (running a second
+/// one panics until the first is dropped).  Others can still
+/// [`replay()`](Headless::replay) traces.  Tests that run frames must not
+/// run in parallel in the same process (doctests each get their own).
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Group, Headless, Shader, ShaderBuilder,
+///     ShapeBuilder, Transform,
+/// };
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     // Vertex colors, no depth (Usually from `shader!()`).
+///     let shader = Shader::new(ShaderBuilder {
+///         tint: false,
+///         gradient: true,
+///         graphic: false,
+///         depth: false,
+///         blend: false,
+///         opengl_frag: "\0",
+///         opengl_vert: "\0",
+///     });
+///     #[rustfmt::skip]
+///     let square = ShapeBuilder::new()
+///         .vert(&[
+///             0.0, 0.0, 1.0, 1.0, 1.0,
+///             0.0, 1.0, 1.0, 1.0, 1.0,
+///             1.0, 0.0, 1.0, 1.0, 1.0,
+///             1.0, 0.0, 1.0, 1.0, 1.0,
+///             0.0, 1.0, 1.0, 1.0, 1.0,
+///             1.0, 1.0, 1.0, 1.0, 1.0,
+///         ])
+///         .face(Transform::new())
+///         .finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &square, &Transform::new().scale(0.5, 0.5, 1.0));
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(1.0, 0.0, 0.0)).await;
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(64, 48);
+/// for _ in 0..2 {
+///     gpu.run(std::time::Duration::from_millis(16));
+/// }
+/// assert_eq!(gpu.raster().pixel(0, 0), SRgba8::new(255, 255, 255, 255));
+/// assert_eq!(gpu.raster().pixel(63, 47), SRgba8::new(255, 0, 0, 255));
+/// ```
+pub struct Headless {
+    raster: Raster<SRgba8>,
+    depth: Vec<f32>,
+    background: [f32; 3],
+    camera: Transform,
+    rasters: Slots<Raster<SRgba8>>,
+    shaders: Slots<Program>,
+    // Triangles of each shape, and how many color components they have.
+    shapes: Slots<(Vec<Vertex>, usize)>,
+    groups: Slots<Vec<Vec<Vertex>>>,
+    // While drawing into a texture: its id, and the screen's raster, depth
+    // buffer and camera.
+    offscreen: Option<(Id, Raster<SRgba8>, Vec<f32>, Transform)>,
+    // Whether this is the `Headless` taking frames from the async thread.
+    live: bool,
+}
+
+impl Headless {
+    /// Create a new headless GPU that renders at `width`×`height` pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        assert!(width > 0 && height > 0);
+        Headless {
+            raster: Raster::with_clear(width, height),
+            depth: vec![1.0; width as usize * height as usize],
+            // Same default as the `window` crate.
+            background: [0.0, 0.0, 1.0],
+            camera: Transform::new(),
+            rasters: Slots::new(Resource::Texture),
+            shaders: Slots::new(Resource::Shader),
+            shapes: Slots::new(Resource::Shape),
+            groups: Slots::new(Resource::Group),
+            offscreen: None,
+            live: false,
+        }
+    }
+
+    /// Request a frame from the async thread and render it.  Blocks until
+    /// the requested [`Frame`](crate::window::Frame) is dropped.
+    ///
+    /// # Panics
+    /// If another `Headless` that has run frames still exists.
+    pub fn run(&mut self, elapsed: std::time::Duration) {
+        if !self.live {
+            assert!(
+                !LIVE.swap(true, Ordering::SeqCst),
+                "Another `Headless` is already running frames"
+            );
+            self.live = true;
+        }
+        self.clear();
+        async_runner(self, elapsed);
+    }
+
+    /// Render the next frame of a trace recorded with
+    /// [`record()`](super::record) instead of requesting a frame from the
+    /// async thread.  Returns `false` at the end of the trace.
+    pub fn replay(&mut self, player: &mut Player) -> std::io::Result<bool> {
+        if let Some(cmds) = player.cmds()? {
+            self.clear();
+            for cmd in cmds {
+                if let Err(error) = self.execute(cmd) {
+                    registry::report(error);
+                }
+            }
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    // Clear to the background color.
+    fn clear(&mut self) {
+        let [r, g, b] = self.background;
+        let clear = SRgba8::new(to_u8(r), to_u8(g), to_u8(b), 255);
+        for pixel in self.raster.pixels_mut() {
+            *pixel = clear;
+        }
+        for depth in self.depth.iter_mut() {
+            *depth = 1.0;
+        }
+    }
+
+    /// Get the output of the most recently rendered frame.
+    pub fn raster(&self) -> &Raster<SRgba8> {
+        &self.raster
+    }
+
+    // Draw a group with an optional texture.
+    fn draw(
+        &mut self,
+        shader: Id,
+        group: Id,
+        texture: Option<Id>,
+        pipeline: Option<Pipeline>,
+    ) -> Result<(), ResourceError> {
+        let aspect = self.aspect();
+        let program = self.shaders.get(shader)?;
+        let pipeline = Pipeline::or_shader(pipeline, program.blend);
+        let texture = match texture {
+            Some(texture) => Some(self.rasters.get(texture)?),
+            None => None,
+        };
+        let entries = self.groups.get(group)?;
+        let projection = if program.depth {
+            Transform::from_mat4([
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / aspect, 0.0, 0.0],
+                [0.0, 0.0, (HORIZON + NEAR) / (NEAR - HORIZON), -1.0],
+                [0.0, 0.0, (2.0 * HORIZON * NEAR) / (NEAR - HORIZON), 0.0],
+            ])
+        } else {
+            Transform::new().scale(1.0, 1.0 / aspect, 1.0)
+        };
+        let coordinates = Transform::new()
+            .scale(2.0, -2.0, -2.0)
+            .translate(-1.0, aspect, 0.0);
+        let matrix = mat4(coordinates * self.camera * projection);
+        let mut target = Target {
+            raster: &mut self.raster,
+            depth: &mut self.depth,
+        };
+        for entry in entries.iter() {
+            for triangle in entry.chunks_exact(3) {
+                target.triangle(program, &pipeline, texture, &matrix, triangle);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Headless {
+    fn drop(&mut self) {
+        if self.live {
+            LIVE.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Backend for Headless {
+    fn aspect(&self) -> f32 {
+        self.raster.height() as f32 / self.raster.width() as f32
+    }
+
+    fn width(&self) -> u32 {
+        self.raster.width()
+    }
+
+    fn execute(&mut self, cmd: GpuCmd) -> Result<(), ResourceError> {
+        use GpuCmd::*;
+        match cmd {
+            Background(r, g, b) => self.background = [r, g, b],
+            Draw(shader, group, pipeline) => {
+                self.draw(shader, group, None, pipeline)?
+            }
+            DrawGraphic(shader, group, raster, pipeline) => {
+                self.draw(shader, group, Some(raster), pipeline)?
+            }
+            SetCamera(camera) => self.camera = camera,
+            SetTint(shader, tint) => {
+                let program = self.shaders.get_mut(shader)?;
+                if let Some(ref mut t) = program.tint {
+                    *t = tint;
+                }
+            }
+            RasterId(raster, id) => self.rasters.store(id, raster),
+            RasterUpdate(id, region, raster) => {
+                let texture = self.rasters.get_mut(id)?;
+                let width = texture.width();
+                blit(texture.as_u8_slice_mut(), width, region, &raster);
+            }
+            RasterDrop(id) => {
+                self.rasters.remove(id)?;
+            }
+            ShaderDrop(id) => {
+                self.shaders.remove(id)?;
+            }
+            ShapeDrop(id) => {
+                self.shapes.remove(id)?;
+            }
+            GroupDrop(id) => {
+                self.groups.remove(id)?;
+            }
+            ShaderId(builder, id) => {
+                let program = Program {
+                    // Uniforms start zeroed, like on the GPU.
+                    tint: if builder.tint { Some([0.0; 4]) } else { None },
+                    gradient: builder.gradient,
+                    graphic: builder.graphic,
+                    depth: builder.depth,
+                    blend: builder.blend,
+                };
+                self.shaders.store(id, program);
+            }
+            ShapeId(builder, id, shader) => {
+                let program = self.shaders.get(shader)?;
+                let shape = triangles(program, builder);
+                self.shapes.store(id, shape);
+            }
+            GroupId(id) => self.groups.store(id, Vec::new()),
+            GroupWrite(group, id, shape, transform) => {
+                let coords = ([0.0, 0.0], [1.0, 1.0]);
+                self.write(group, id, shape, transform, coords, None)?;
+            }
+            GroupWriteTex(group, id, shape, transform, coords) => {
+                self.write(group, id, shape, transform, coords, None)?;
+            }
+            InstanceWrite(group, shape, instances) => {
+                let coords = ([0.0, 0.0], [1.0, 1.0]);
+                for (id, transform, tint) in instances {
+                    self.write(group, id, shape, transform, coords, tint)?;
+                }
+            }
+            Capture(capture) => captured(capture, self.raster.clone()),
+            SetTarget(target) => self.target(target)?,
+            PostProcess(passes) => {
+                for pass in passes.iter() {
+                    let lut = match pass {
+                        post::Pass::ColorGrade(lut, _) => {
+                            Some(self.rasters.get(*lut)?)
+                        }
+                        _ => None,
+                    };
+                    post::apply(&mut self.raster, pass, lut);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Headless {
+    // Switch between drawing into a texture and on the screen.
+    fn target(
+        &mut self,
+        target: Option<(Id, u32, u32, [f32; 4])>,
+    ) -> Result<(), ResourceError> {
+        if let Some((id, raster, depth, camera)) = self.offscreen.take() {
+            let texture = std::mem::replace(&mut self.raster, raster);
+            // The texture may have been dropped while drawing into it.
+            if let Ok(rendered) = self.rasters.get_mut(id) {
+                *rendered = texture;
+            }
+            self.depth = depth;
+            self.camera = camera;
+        }
+        if let Some((id, _width, _height, color)) = target {
+            let texture = std::mem::replace(
+                self.rasters.get_mut(id)?,
+                Raster::with_clear(0, 0),
+            );
+            let size = texture.width() as usize * texture.height() as usize;
+            let raster = std::mem::replace(&mut self.raster, texture);
+            let depth = std::mem::replace(&mut self.depth, vec![1.0; size]);
+            self.offscreen = Some((id, raster, depth, self.camera));
+            self.camera = Transform::new();
+            let [r, g, b, a] = color;
+            let clear = SRgba8::new(to_u8(r), to_u8(g), to_u8(b), to_u8(a));
+            for pixel in self.raster.pixels_mut() {
+                *pixel = clear;
+            }
+        }
+        Ok(())
+    }
+
+    // Write a transformed shape into a group, multiplying its vertex colors
+    // by `tint`.
+    fn write(
+        &mut self,
+        group: Id,
+        id: u32,
+        shape: Id,
+        transform: Transform,
+        coords: ([f32; 2], [f32; 2]),
+        tint: Option<[u8; 4]>,
+    ) -> Result<(), ResourceError> {
+        let (ref vertices, components) = *self.shapes.get(shape)?;
+        let tint = tint.unwrap_or([255; 4]);
+        let entry = vertices
+            .iter()
+            .map(|vertex| Vertex {
+                pos: transform * vertex.pos,
+                col: {
+                    let mut col = vertex.col;
+                    for (c, t) in col.iter_mut().zip(tint).take(components) {
+                        *c *= f32::from(t) / 255.0;
+                    }
+                    col
+                },
+                tex: [
+                    vertex.tex[0] * coords.1[0] + coords.0[0],
+                    vertex.tex[1] * coords.1[1] + coords.0[1],
+                ],
+            })
+            .collect();
+        let entries = self.groups.get_mut(group)?;
+        if id as usize >= entries.len() {
+            entries.resize_with(id as usize + 1, Vec::new);
+        }
+        entries[id as usize] = entry;
+        Ok(())
+    }
+}
+
+// Convert the faces of a shape into a list of triangles, with the number of
+// color components they have.
+fn triangles(program: &Program, builder: ShapeBuilder) -> (Vec<Vertex>, usize) {
+    let dimensions = if program.depth { 3 } else { 2 };
+    let components = match (program.gradient, program.blend) {
+        (false, _) => 0,
+        (true, false) => 3,
+        (true, true) => 4,
+    };
+    let graphic = if program.graphic { 2 } else { 0 };
+    let stride = dimensions + graphic + components;
+
+    let mut vertices = Vec::new();
+    let mut shape = Vec::new();
+    for face in builder.faces {
+        if let Some(v) = face.vertices {
+            vertices = v;
+        }
+        let transform = match face.transform {
+            Some(transform) => transform,
+            None => continue,
+        };
+        for v in vertices.chunks_exact(stride) {
+            let z = if dimensions == 3 { v[2] } else { 0.0 };
+            let mut col = [1.0; 4];
+            for (i, c) in v[stride - components..].iter().enumerate() {
+                col[i] = *c;
+            }
+            let tex = if graphic == 2 {
+                [v[dimensions], v[dimensions + 1]]
+            } else {
+                [0.0, 0.0]
+            };
+            shape.push(Vertex {
+                pos: transform * [v[0], v[1], z],
+                col,
+                tex,
+            });
+        }
+    }
+    (shape, components)
+}
+
+// Create a plotter for a `width`×`height` coverage mask, and get the length of
+// its rows.  footile writes 8 pixels at a time, so they're padded to a
+// multiple of 8.
+pub(super) fn mask(width: u32, height: u32) -> (Plotter<Matte8>, u32) {
+    let stride = width.div_ceil(8) * 8;
+    (Plotter::new(Raster::with_clear(stride, height)), stride)
+}
+
+fn to_u8(channel: f32) -> u8 {
+    (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Sample a texture with nearest filtering and repeat wrapping.
+fn sample(texture: &Raster<SRgba8>, tex: [f32; 2]) -> [f32; 4] {
+    let w = texture.width();
+    let h = texture.height();
+    // A render target's texture is empty while it's being drawn on.
+    if w == 0 || h == 0 {
+        return [0.0; 4];
+    }
+    let x = ((tex[0] - tex[0].floor()) * w as f32) as u32;
+    let y = ((tex[1] - tex[1].floor()) * h as f32) as u32;
+    let i = (y.min(h - 1) * w + x.min(w - 1)) as usize * 4;
+    let p = &texture.as_u8_slice()[i..i + 4];
+    [
+        f32::from(p[0]) / 255.0,
+        f32::from(p[1]) / 255.0,
+        f32::from(p[2]) / 255.0,
+        f32::from(p[3]) / 255.0,
+    ]
+}
+
+// Area of the parallelogram formed by three points (sign is winding).
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])
+}
+
+// Color and depth buffers to rasterize into.
+struct Target<'a> {
+    raster: &'a mut Raster<SRgba8>,
+    depth: &'a mut [f32],
+}
+
+impl Target<'_> {
+    // Rasterize one triangle.
+    fn triangle(
+        &mut self,
+        program: &Program,
+        pipeline: &Pipeline,
+        texture: Option<&Raster<SRgba8>>,
+        matrix: &[[f32; 4]; 4],
+        triangle: &[Vertex],
+    ) {
+        let width = self.raster.width();
+        let height = self.raster.height();
+
+        // Vertex shader (no clipping, so drop triangles behind the camera).
+        let mut clip = [[0.0f32; 4]; 3];
+        for (c, vertex) in clip.iter_mut().zip(triangle) {
+            let [x, y, z] = vertex.pos;
+            for (row, out) in c.iter_mut().enumerate() {
+                *out = matrix[0][row] * x
+                    + matrix[1][row] * y
+                    + matrix[2][row] * z
+                    + matrix[3][row];
+            }
+            if c[3] <= 0.0 {
+                return;
+            }
+        }
+        let ndc = [
+            [clip[0][0] / clip[0][3], clip[0][1] / clip[0][3]],
+            [clip[1][0] / clip[1][3], clip[1][1] / clip[1][3]],
+            [clip[2][0] / clip[2][3], clip[2][1] / clip[2][3]],
+        ];
+        // Counter-clockwise triangles face the camera.
+        let winding = edge(ndc[0], ndc[1], ndc[2]);
+        let culled = match pipeline.cull {
+            Cull::None => winding == 0.0,
+            Cull::Back => winding <= 0.0,
+            Cull::Front => winding >= 0.0,
+        };
+        if culled {
+            return;
+        }
+        let screen = [
+            [
+                (ndc[0][0] + 1.0) * 0.5 * width as f32,
+                (1.0 - ndc[0][1]) * 0.5 * height as f32,
+            ],
+            [
+                (ndc[1][0] + 1.0) * 0.5 * width as f32,
+                (1.0 - ndc[1][1]) * 0.5 * height as f32,
+            ],
+            [
+                (ndc[2][0] + 1.0) * 0.5 * width as f32,
+                (1.0 - ndc[2][1]) * 0.5 * height as f32,
+            ],
+        ];
+
+        // Bounding box of the triangle on the raster.
+        let left = screen.iter().map(|p| p[0]).fold(f32::MAX, f32::min);
+        let top = screen.iter().map(|p| p[1]).fold(f32::MAX, f32::min);
+        let right = screen.iter().map(|p| p[0]).fold(f32::MIN, f32::max);
+        let bottom = screen.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+        let left = left.floor().max(0.0) as u32;
+        let top = top.floor().max(0.0) as u32;
+        let right = (right.ceil().max(0.0) as u32).min(width);
+        let bottom = (bottom.ceil().max(0.0) as u32).min(height);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        // Get coverage from footile.
+        let (ox, oy) = (left as f32, top as f32);
+        let path = Path2D::default()
+            .absolute()
+            .move_to(screen[0][0] - ox, screen[0][1] - oy)
+            .line_to(screen[1][0] - ox, screen[1][1] - oy)
+            .line_to(screen[2][0] - ox, screen[2][1] - oy)
+            .close()
+            .finish();
+        let (mut plotter, stride) = mask(right - left, bottom - top);
+        plotter.fill(FillRule::NonZero, &path, Matte8::new(255));
+        let mask = plotter.raster();
+
+        // Fragment shader.
+        let area = edge(screen[0], screen[1], screen[2]);
+        let coverage = mask.as_u8_slice();
+        for y in top..bottom {
+            for x in left..right {
+                let m = ((y - top) * stride + (x - left)) as usize;
+                // Only draw pixels that are mostly covered, like the GPU.
+                if coverage[m] < 128 {
+                    continue;
+                }
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let mut bary = [
+                    (edge(screen[1], screen[2], p) / area).max(0.0),
+                    (edge(screen[2], screen[0], p) / area).max(0.0),
+                    (edge(screen[0], screen[1], p) / area).max(0.0),
+                ];
+                let sum: f32 = bary.iter().sum();
+                if sum <= 0.0 {
+                    continue;
+                }
+                for b in bary.iter_mut() {
+                    *b /= sum;
+                }
+                let index = (y * width + x) as usize;
+                if program.depth {
+                    let z = (0..3)
+                        .map(|i| bary[i] * clip[i][2] / clip[i][3])
+                        .sum::<f32>()
+                        * 0.5
+                        + 0.5;
+                    if !(0.0..=1.0).contains(&z)
+                        || (pipeline.depth_test && z >= self.depth[index])
+                    {
+                        continue;
+                    }
+                    if pipeline.depth_write {
+                        self.depth[index] = z;
+                    }
+                }
+                // Perspective-correct interpolation.
+                let mut persp = [
+                    bary[0] / clip[0][3],
+                    bary[1] / clip[1][3],
+                    bary[2] / clip[2][3],
+                ];
+                let sum: f32 = persp.iter().sum();
+                for b in persp.iter_mut() {
+                    *b /= sum;
+                }
+                let mut color = [1.0f32; 4];
+                if program.gradient {
+                    for (c, channel) in color.iter_mut().enumerate() {
+                        *channel *= (0..3)
+                            .map(|i| persp[i] * triangle[i].col[c])
+                            .sum::<f32>();
+                    }
+                }
+                if let (true, Some(texture)) = (program.graphic, texture) {
+                    let tex = [
+                        (0..3).map(|i| persp[i] * triangle[i].tex[0]).sum(),
+                        (0..3).map(|i| persp[i] * triangle[i].tex[1]).sum(),
+                    ];
+                    let texel = sample(texture, tex);
+                    for (channel, t) in color.iter_mut().zip(texel.iter()) {
+                        *channel *= t;
+                    }
+                }
+                if let Some(tint) = program.tint {
+                    for (channel, t) in color.iter_mut().zip(tint.iter()) {
+                        *channel *= t;
+                    }
+                }
+                self.pixel(index, color, pipeline.blend);
+            }
+        }
+    }
+
+    // Write a pixel, blending with the same functions as `gl::pipeline()`.
+    fn pixel(&mut self, index: usize, color: [f32; 4], blend: Blend) {
+        let dst = &mut self.raster.as_u8_slice_mut()[index * 4..index * 4 + 4];
+        let alpha = color[3];
+        let old = |i: usize| f32::from(dst[i]) / 255.0;
+        let color = match blend {
+            Blend::Opaque => color,
+            Blend::Alpha => [
+                color[0] * alpha + old(0) * (1.0 - alpha),
+                color[1] * alpha + old(1) * (1.0 - alpha),
+                color[2] * alpha + old(2) * (1.0 - alpha),
+                alpha * alpha + old(3) * old(3),
+            ],
+            Blend::Additive => [
+                color[0] * alpha + old(0),
+                color[1] * alpha + old(1),
+                color[2] * alpha + old(2),
+                alpha * alpha + old(3) * old(3),
+            ],
+        };
+        for (d, c) in dst.iter_mut().zip(color.iter()) {
+            *d = to_u8(*c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::{
+        color::SRgb32, doctest::builder, Canvas, Group, Shader,
+    };
+    use crate::window::Frame;
+    use std::time::Duration;
+
+    #[test]
+    #[cfg(feature = "task")]
+    fn run_frames() {
+        std::thread::spawn(|| {
+            let shader = Shader::new(builder());
+            let green = [0.0, 1.0, 0.0, 1.0];
+            let square = ShapeBuilder::quad(&builder(), green).finish(&shader);
+            let mut group = Group::new();
+            group.write(0, &square, &Transform::new().scale(0.5, 0.5, 1.0));
+            crate::task::exec!({
+                let mut frame = Frame::new(SRgb32::new(1.0, 0.0, 0.0)).await;
+                frame.draw(&shader, &group);
+            });
+        });
+
+        let mut gpu = Headless::new(8, 8);
+        for _ in 0..2 {
+            gpu.run(Duration::from_millis(16));
+        }
+        assert_eq!(gpu.raster().pixel(1, 1), SRgba8::new(0, 255, 0, 255));
+        assert_eq!(gpu.raster().pixel(6, 6), SRgba8::new(255, 0, 0, 255));
+
+        // A second `Headless` would take frames from the first one.
+        let second = std::panic::catch_unwind(|| {
+            Headless::new(8, 8).run(Duration::from_millis(16))
+        });
+        assert!(second.is_err());
+
+        // Once the first one is dropped, another one can run (without the
+        // resources that were created on the first one).
+        drop(gpu);
+        Headless::new(8, 8).run(Duration::from_millis(16));
+    }
+}
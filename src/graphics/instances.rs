@@ -0,0 +1,162 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{sprite::key, GpuCmd, Group, Internal, Shape, Transform};
+use pix::{el::Pixel, rgb::SRgba8};
+
+/// Many copies of one [`Shape`], each with its own transform and tint, drawn
+/// with [`Canvas::draw_instances()`](super::Canvas::draw_instances).
+///
+/// Only instances that changed since they were last drawn are sent to the
+/// GPU.  Tints multiply vertex colors, so they only apply with shaders that
+/// have a gradient.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Headless, Instances, Shader, ShapeBuilder,
+///     Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     let square = ShapeBuilder::quad(&builder(), [1.0; 4]).finish(&shader);
+///     // A 4x4 grid of squares, with a red one in the corner.
+///     let mut grid = Instances::new(square);
+///     for i in 0..16 {
+///         let (x, y) = ((i % 4) as f32, (i / 4) as f32);
+///         let transform = Transform::new()
+///             .scale(0.25, 0.25, 1.0)
+///             .translate(x * 0.25, y * 0.25, 0.0);
+///         grid.push(&transform);
+///     }
+///     grid.set_tint(0, SRgba8::new(255, 0, 0, 255));
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.draw_instances(&shader, &mut grid);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(0, 0), SRgba8::new(255, 0, 0, 255));
+/// assert_eq!(gpu.raster().pixel(7, 7), SRgba8::new(255, 255, 255, 255));
+/// ```
+pub struct Instances {
+    shape: Shape,
+    group: Group,
+    instances: Vec<(Transform, Option<[u8; 4]>)>,
+    // Whether each instance changed since it was last written.
+    changed: Vec<bool>,
+    // Number of instances in the group that aren't hidden.
+    written: usize,
+}
+
+impl Instances {
+    /// Create an empty set of instances of a `Shape`.
+    pub fn new(shape: Shape) -> Self {
+        Instances {
+            shape,
+            group: Group::new(),
+            instances: Vec::new(),
+            changed: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Get the number of instances.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns true if there are no instances.
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Add an instance, returning its index.  The `transform` is applied to
+    /// the shape.
+    pub fn push(&mut self, transform: &Transform) -> usize {
+        self.instances.push((*transform, None));
+        self.changed.push(true);
+        self.instances.len() - 1
+    }
+
+    /// Change the transform of an instance.
+    pub fn set(&mut self, index: usize, transform: &Transform) {
+        self.instances[index].0 = *transform;
+        self.changed[index] = true;
+    }
+
+    /// Multiply the vertex colors of an instance by `tint`.
+    pub fn set_tint<P: Pixel>(&mut self, index: usize, tint: P)
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        let tint: SRgba8 = tint.convert();
+        self.instances[index].1 = Some(key(tint));
+        self.changed[index] = true;
+    }
+
+    /// Stop tinting an instance.
+    pub fn clear_tint(&mut self, index: usize) {
+        self.instances[index].1 = None;
+        self.changed[index] = true;
+    }
+
+    /// Remove the instances after the first `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.instances.truncate(len);
+        self.changed.truncate(len);
+    }
+
+    /// Send the instances that changed to the GPU, and get the group to draw
+    /// them with (for drawing with [`Canvas::draw_with()`] or
+    /// [`Canvas::draw_graphic()`]).
+    ///
+    /// [`Canvas::draw_with()`]: super::Canvas::draw_with
+    /// [`Canvas::draw_graphic()`]: super::Canvas::draw_graphic
+    pub fn group(&mut self) -> &Group {
+        let mut writes = Vec::new();
+        for (id, (instance, changed)) in self
+            .instances
+            .iter()
+            .zip(self.changed.iter_mut())
+            .enumerate()
+        {
+            if *changed {
+                writes.push((id as u32, instance.0, instance.1));
+                self.group.record(id as u32, self.shape.1, &instance.0);
+                *changed = false;
+            }
+        }
+        // Hide instances left over from before truncating by shrinking them
+        // away.
+        let hidden = Transform::new().scale(0.0, 0.0, 0.0);
+        for id in self.instances.len()..self.written {
+            writes.push((id as u32, hidden, None));
+            self.group.record(id as u32, self.shape.1, &hidden);
+        }
+        self.written = self.instances.len();
+        if !writes.is_empty() {
+            let internal = Internal::new_lazy();
+            let mut cmds = internal.cmds.lock().unwrap();
+            cmds.push(GpuCmd::InstanceWrite(
+                self.group.0,
+                self.shape.0,
+                writes,
+            ));
+        }
+        &self.group
+    }
+}
@@ -0,0 +1,240 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{gltf, obj, ShaderBuilder, ShapeBuilder, Transform};
+use pix::{rgb::SRgba8, Raster};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Cursor, Error as IoError},
+    path::Path,
+};
+
+/// An error from loading a [`Mesh`].
+#[derive(Debug)]
+pub enum MeshError {
+    /// The file, or a file it refers to, couldn't be read.
+    Io(IoError),
+    /// The file isn't valid, with a description of what's wrong.
+    Invalid(String),
+    /// The file uses a feature that isn't supported, with its name.
+    Unsupported(String),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            MeshError::Io(error) => write!(f, "Couldn't read mesh: {}", error),
+            MeshError::Invalid(what) => write!(f, "Invalid mesh: {}", what),
+            MeshError::Unsupported(feature) => {
+                write!(f, "Unsupported mesh feature: {}", feature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MeshError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MeshError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for MeshError {
+    fn from(error: IoError) -> Self {
+        MeshError::Io(error)
+    }
+}
+
+/// Triangles loaded from a Wavefront OBJ or glTF file, with the color and
+/// texture of their material.
+///
+/// Files are loaded as one `Mesh` for each material (OBJ) or primitive
+/// (glTF).  Only the base color and texture of materials are used, and
+/// textures must be PNG images.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Group, Headless, Mesh, Shader, Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// let dir = std::env::temp_dir().join("cala-mesh-obj");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("square.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+/// std::fs::write(
+///     dir.join("square.obj"),
+///     "mtllib square.mtl\n\
+///      v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+///      usemtl red\n\
+///      f 1 4 3 2\n",
+/// )
+/// .unwrap();
+/// let meshes = Mesh::load_obj(dir.join("square.obj")).unwrap();
+/// assert_eq!(meshes.len(), 1);
+/// assert_eq!(meshes[0].triangles(), 2);
+///
+/// std::thread::spawn(move || {
+///     let square = meshes[0].builder(&builder());
+///     let shader = Shader::new(builder());
+///     let square = square.finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &square, &Transform::new().scale(0.5, 0.5, 1.0));
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(1, 1), SRgba8::new(255, 0, 0, 255));
+/// ```
+pub struct Mesh {
+    pub(super) positions: Vec<[f32; 3]>,
+    pub(super) texcoords: Vec<[f32; 2]>,
+    pub(super) colors: Vec<[f32; 4]>,
+    pub(super) texture: Option<Raster<SRgba8>>,
+}
+
+impl Mesh {
+    /// Load the meshes from a Wavefront OBJ file, with materials from the
+    /// MTL files it refers to.
+    ///
+    /// Faces with more than three vertices are split into triangles, and
+    /// vertex colors (after the position on `v` lines) are supported.
+    /// Lines, points and free-form curves and surfaces aren't.
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Mesh>, MeshError> {
+        obj::load(path.as_ref())
+    }
+
+    /// Load the meshes in the default scene of a glTF 2.0 file (`.gltf` with
+    /// embedded base64 buffers, or binary `.glb`), with node transforms
+    /// applied.
+    ///
+    /// Only triangle primitives are supported, and external buffers and
+    /// images, sparse accessors and required extensions aren't.
+    ///
+    /// ```rust
+    /// use cala::graphics::{Mesh, MeshError};
+    ///
+    /// let dir = std::env::temp_dir().join("cala-mesh-gltf");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let path = dir.join("triangle.gltf");
+    /// let gltf = r#"{
+    ///     "asset": { "version": "2.0" },
+    ///     "scenes": [{ "nodes": [0] }],
+    ///     "nodes": [{ "mesh": 0, "scale": [2, 2, 2] }],
+    ///     "meshes": [{
+    ///         "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }]
+    ///     }],
+    ///     "materials": [{
+    ///         "pbrMetallicRoughness": { "baseColorFactor": [0, 1, 0, 1] }
+    ///     }],
+    ///     "accessors": [{
+    ///         "bufferView": 0, "componentType": 5126, "count": 3,
+    ///         "type": "VEC3"
+    ///     }],
+    ///     "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+    ///     "buffers": [{
+    ///         "byteLength": 36,
+    ///         "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAAAAAAAAgD8AAAAAAACAPwAAAAAAAAAA"
+    ///     }]
+    /// }"#;
+    /// std::fs::write(&path, gltf).unwrap();
+    /// let meshes = Mesh::load_gltf(&path).unwrap();
+    /// assert_eq!(meshes.len(), 1);
+    /// assert_eq!(meshes[0].triangles(), 1);
+    ///
+    /// // Buffers must be embedded.
+    /// std::fs::write(&path, gltf.replace("data:application", "triangle.bin"))
+    ///     .unwrap();
+    /// match Mesh::load_gltf(&path) {
+    ///     Err(MeshError::Unsupported(feature)) => {
+    ///         assert_eq!(feature, "external buffers in glTF files")
+    ///     }
+    ///     _ => panic!("External buffers aren't supported"),
+    /// }
+    ///
+    /// // Accessors must fit in their buffer view.
+    /// let huge = r#""count": 18446744073709551615"#;
+    /// std::fs::write(&path, gltf.replace(r#""count": 3"#, huge)).unwrap();
+    /// match Mesh::load_gltf(&path) {
+    ///     Err(MeshError::Invalid(what)) => {
+    ///         assert_eq!(what, "glTF: accessor out of bounds")
+    ///     }
+    ///     _ => panic!("Accessor is out of bounds"),
+    /// }
+    ///
+    /// // JSON can't nest deeply enough to overflow the stack.
+    /// std::fs::write(&path, "[".repeat(200_000)).unwrap();
+    /// match Mesh::load_gltf(&path) {
+    ///     Err(MeshError::Invalid(what)) => {
+    ///         assert_eq!(what, "glTF: JSON nested too deeply")
+    ///     }
+    ///     _ => panic!("JSON is nested too deeply"),
+    /// }
+    /// ```
+    pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<Vec<Mesh>, MeshError> {
+        gltf::load(path.as_ref())
+    }
+
+    /// Get the number of triangles.
+    pub fn triangles(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    /// Get the texture of the mesh's material, for drawing with
+    /// [`Canvas::draw_graphic()`](super::Canvas::draw_graphic).
+    pub fn texture(&self) -> Option<&Raster<SRgba8>> {
+        self.texture.as_ref()
+    }
+
+    /// Get a `ShapeBuilder` with the vertices of the mesh, in the layout of
+    /// `shader` (positions without depth drop their Z coordinate).
+    pub fn builder(&self, shader: &ShaderBuilder) -> ShapeBuilder {
+        let mut vertices = Vec::new();
+        for i in 0..self.positions.len() {
+            let [x, y, z] = self.positions[i];
+            vertices.extend_from_slice(&[x, y]);
+            if shader.depth {
+                vertices.push(z);
+            }
+            if shader.graphic {
+                vertices.extend_from_slice(&self.texcoords[i]);
+            }
+            if shader.gradient {
+                let color = &self.colors[i];
+                let components = if shader.blend { 4 } else { 3 };
+                vertices.extend_from_slice(&color[..components]);
+            }
+        }
+        ShapeBuilder::new().vert(&vertices).face(Transform::new())
+    }
+}
+
+// Decode a PNG texture.
+pub(super) fn texture(bytes: Vec<u8>) -> Result<Raster<SRgba8>, MeshError> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Err(MeshError::Unsupported("JPEG textures".to_string()));
+    }
+    let invalid = |error| MeshError::Invalid(format!("PNG: {:?}", error));
+    let decoder = png_pong::Decoder::new(Cursor::new(bytes))
+        .map_err(invalid)?
+        .into_steps();
+    match decoder.last() {
+        Some(step) => Ok(Raster::from(step.map_err(invalid)?.raster)),
+        None => Err(MeshError::Invalid("PNG has no image".to_string())),
+    }
+}
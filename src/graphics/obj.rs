@@ -0,0 +1,274 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::mesh::{texture, Mesh, MeshError};
+use std::{collections::HashMap, path::Path};
+
+// The parts of an MTL material that are used.
+struct Material {
+    color: [f32; 4],
+    texture: Option<String>,
+}
+
+// Get an error for a line of a file.
+fn invalid(path: &Path, line: usize, what: &str) -> MeshError {
+    MeshError::Invalid(format!("{}:{}: {}", path.display(), line + 1, what))
+}
+
+// Parse the numbers after the keyword of a line.
+fn numbers<'a>(
+    words: impl Iterator<Item = &'a str>,
+    path: &Path,
+    line: usize,
+) -> Result<Vec<f32>, MeshError> {
+    words
+        .map(|word| {
+            word.parse()
+                .map_err(|_| invalid(path, line, "expected a number"))
+        })
+        .collect()
+}
+
+// Resolve a 1-based (or negative, from the end) index into a list.
+fn index(
+    word: &str,
+    len: usize,
+    path: &Path,
+    line: usize,
+) -> Result<usize, MeshError> {
+    let index: isize = word
+        .parse()
+        .map_err(|_| invalid(path, line, "expected an index"))?;
+    let index = if index < 0 {
+        len as isize + index
+    } else {
+        index - 1
+    };
+    if index < 0 || index as usize >= len {
+        return Err(invalid(path, line, "index out of range"));
+    }
+    Ok(index as usize)
+}
+
+pub(super) fn load(path: &Path) -> Result<Vec<Mesh>, MeshError> {
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    // Positions with their vertex colors.
+    let mut positions: Vec<([f32; 3], [f32; 3])> = Vec::new();
+    let mut texcoords: Vec<[f32; 2]> = Vec::new();
+    let mut materials = HashMap::new();
+    // Meshes by material, in the order they're first used.
+    let mut meshes: Vec<(Option<String>, Mesh)> = Vec::new();
+    let mut current = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => {
+                let v = numbers(words, path, number)?;
+                let color = match v.len() {
+                    3 | 4 => [1.0; 3],
+                    6 => [v[3], v[4], v[5]],
+                    _ => {
+                        return Err(invalid(path, number, "expected 3 numbers"))
+                    }
+                };
+                positions.push(([v[0], v[1], v[2]], color));
+            }
+            "vt" => {
+                let vt = numbers(words, path, number)?;
+                if vt.is_empty() || vt.len() > 3 {
+                    return Err(invalid(path, number, "expected 2 numbers"));
+                }
+                // OBJ texture coordinates start at the bottom.
+                texcoords.push([vt[0], 1.0 - vt.get(1).unwrap_or(&0.0)]);
+            }
+            "f" => {
+                let mut vertices = Vec::new();
+                for word in words {
+                    let mut indices = word.split('/');
+                    let position = indices.next().unwrap_or("");
+                    let position =
+                        index(position, positions.len(), path, number)?;
+                    let texcoord = match indices.next() {
+                        None | Some("") => None,
+                        Some(texcoord) => Some(index(
+                            texcoord,
+                            texcoords.len(),
+                            path,
+                            number,
+                        )?),
+                    };
+                    vertices.push((position, texcoord));
+                }
+                if vertices.len() < 3 {
+                    return Err(invalid(path, number, "expected 3 vertices"));
+                }
+                let mesh = match current {
+                    Some(mesh) => mesh,
+                    None => {
+                        meshes.push((None, empty()));
+                        current = Some(meshes.len() - 1);
+                        meshes.len() - 1
+                    }
+                };
+                let mesh = &mut meshes[mesh].1;
+                // Split the face into a fan of triangles.
+                for i in 1..vertices.len() - 1 {
+                    for &(position, texcoord) in
+                        [vertices[0], vertices[i], vertices[i + 1]].iter()
+                    {
+                        let (position, [r, g, b]) = positions[position];
+                        mesh.positions.push(position);
+                        mesh.colors.push([r, g, b, 1.0]);
+                        mesh.texcoords.push(match texcoord {
+                            Some(texcoord) => texcoords[texcoord],
+                            None => [0.0, 0.0],
+                        });
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = words.next().map(str::to_string);
+                current = match meshes.iter().position(|(m, _)| *m == name) {
+                    Some(mesh) => Some(mesh),
+                    None => {
+                        meshes.push((name, empty()));
+                        Some(meshes.len() - 1)
+                    }
+                };
+            }
+            "mtllib" => {
+                for file in words {
+                    mtl(&dir.join(file), &mut materials)?;
+                }
+            }
+            // Normals, and names of objects, groups and smoothing groups.
+            "vn" | "o" | "g" | "s" => {}
+            "l" | "p" => {
+                return Err(MeshError::Unsupported(
+                    "lines and points in OBJ files".to_string(),
+                ))
+            }
+            "vp" | "cstype" | "deg" | "bmat" | "step" | "curv" | "curv2"
+            | "surf" | "parm" | "trim" | "hole" | "scrv" | "sp" | "end"
+            | "con" => {
+                return Err(MeshError::Unsupported(
+                    "free-form curves and surfaces in OBJ files".to_string(),
+                ))
+            }
+            _ => {
+                return Err(MeshError::Unsupported(format!(
+                    "`{}` in OBJ files",
+                    keyword
+                )))
+            }
+        }
+    }
+
+    let mut loaded = Vec::new();
+    for (name, mut mesh) in meshes {
+        if mesh.positions.is_empty() {
+            continue;
+        }
+        if let Some(name) = name {
+            let material = materials.get(&name).ok_or_else(|| {
+                MeshError::Invalid(format!("Unknown material `{}`", name))
+            })?;
+            for color in mesh.colors.iter_mut() {
+                for (channel, m) in color.iter_mut().zip(material.color) {
+                    *channel *= m;
+                }
+            }
+            if let Some(ref file) = material.texture {
+                mesh.texture = Some(texture(std::fs::read(dir.join(file))?)?);
+            }
+        }
+        loaded.push(mesh);
+    }
+    Ok(loaded)
+}
+
+fn empty() -> Mesh {
+    Mesh {
+        positions: Vec::new(),
+        texcoords: Vec::new(),
+        colors: Vec::new(),
+        texture: None,
+    }
+}
+
+// Load the materials from an MTL file.
+fn mtl(
+    path: &Path,
+    materials: &mut HashMap<String, Material>,
+) -> Result<(), MeshError> {
+    let source = std::fs::read_to_string(path)?;
+    let mut current = None;
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = words.collect::<Vec<_>>().join(" ");
+            materials.insert(
+                name.clone(),
+                Material {
+                    color: [1.0; 4],
+                    texture: None,
+                },
+            );
+            current = Some(name);
+            continue;
+        }
+        let material = match current {
+            Some(ref name) => materials.get_mut(name).unwrap(),
+            None => return Err(invalid(path, number, "expected `newmtl`")),
+        };
+        match keyword {
+            "Kd" => {
+                let kd = numbers(words, path, number)?;
+                if kd.len() != 3 {
+                    return Err(invalid(path, number, "expected 3 numbers"));
+                }
+                material.color[..3].copy_from_slice(&kd);
+            }
+            "d" | "Tr" => {
+                let d = numbers(words, path, number)?;
+                if d.len() != 1 {
+                    return Err(invalid(path, number, "expected a number"));
+                }
+                material.color[3] =
+                    if keyword == "d" { d[0] } else { 1.0 - d[0] };
+            }
+            "map_Kd" => {
+                let file = words.collect::<Vec<_>>();
+                if file.iter().any(|word| word.starts_with('-')) {
+                    return Err(MeshError::Unsupported(
+                        "texture options in MTL files".to_string(),
+                    ));
+                }
+                material.texture = Some(file.join(" "));
+            }
+            // Lighting isn't supported, so the rest doesn't change how the
+            // material looks.
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,328 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{sprite::key, Canvas, Group, Instances, Shape, Texture, Transform};
+use nanorand::{WyRand, RNG};
+use pix::{el::Pixel, rgb::SRgba8};
+use std::{sync::Arc, time::Duration};
+
+/// How a [`Particles`] system spawns particles, and how they change over
+/// their life.
+///
+/// Ranges are given as the lowest and highest value, and each particle gets
+/// a random value between them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Emitter {
+    position: [f32; 3],
+    rate: f32,
+    lifetime: (Duration, Duration),
+    velocity: ([f32; 3], [f32; 3]),
+    gravity: [f32; 3],
+    colors: Option<([u8; 4], [u8; 4])>,
+    sizes: (f32, f32),
+    max_particles: usize,
+    seed: u64,
+}
+
+impl Emitter {
+    /// Create an `Emitter` at the origin that spawns `rate` particles per
+    /// second, which stay still for 1 second at size 1, with at most 1000
+    /// alive at once.
+    pub fn new(rate: f32) -> Self {
+        assert!(rate >= 0.0);
+        Emitter {
+            position: [0.0; 3],
+            rate,
+            lifetime: (Duration::from_secs(1), Duration::from_secs(1)),
+            velocity: ([0.0; 3], [0.0; 3]),
+            gravity: [0.0; 3],
+            colors: None,
+            sizes: (1.0, 1.0),
+            max_particles: 1000,
+            seed: 0,
+        }
+    }
+
+    /// Set where particles are spawned.
+    pub fn position(mut self, position: [f32; 3]) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Set how long particles live.
+    pub fn lifetime(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max && min > Duration::from_secs(0));
+        self.lifetime = (min, max);
+        self
+    }
+
+    /// Set the velocity particles are spawned with (per second), for each
+    /// axis.
+    pub fn velocity(mut self, min: [f32; 3], max: [f32; 3]) -> Self {
+        self.velocity = (min, max);
+        self
+    }
+
+    /// Set the acceleration of all particles (per second, per second).
+    pub fn gravity(mut self, gravity: [f32; 3]) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Tint particles with a color that fades from `start` to `end` over
+    /// their life.  Tints multiply vertex colors, so they only apply with
+    /// shaders that have a gradient.
+    pub fn color<P: Pixel>(mut self, start: P, end: P) -> Self
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        let start: SRgba8 = start.convert();
+        let end: SRgba8 = end.convert();
+        self.colors = Some((key(start), key(end)));
+        self
+    }
+
+    /// Set the scale of particles, which changes from `start` to `end` over
+    /// their life.
+    pub fn size(mut self, start: f32, end: f32) -> Self {
+        self.sizes = (start, end);
+        self
+    }
+
+    /// Set the most particles alive at once (default: 1000).  No particles
+    /// are spawned while there are this many.
+    pub fn max_particles(mut self, max_particles: usize) -> Self {
+        self.max_particles = max_particles;
+        self
+    }
+
+    /// Set the seed of the random values particles get (default: 0).
+    /// Systems with the same emitter advanced by the same times have the
+    /// same particles.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+struct Particle {
+    position: [f32; 3],
+    velocity: [f32; 3],
+    age: f32,
+    lifetime: f32,
+}
+
+/// Particles spawned by an [`Emitter`], each drawn as an instance of a
+/// [`Shape`] with [`Canvas::draw_particles()`](super::Canvas::draw_particles).
+///
+/// The shape is scaled by each particle's size around its origin, and moved
+/// to the particle's position (so shapes centered on the origin are centered
+/// on their particles).
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Emitter, Headless, Particles, Shader, Shape,
+///     ShapeBuilder,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+/// use std::time::Duration;
+///
+/// fn square(shader: &Shader) -> Shape {
+///     ShapeBuilder::quad(&builder(), [1.0; 4]).finish(shader)
+/// }
+///
+/// let red = SRgba8::new(255, 0, 0, 255);
+/// std::thread::spawn(move || {
+///     let shader = Shader::new(builder());
+///     // Red squares that fall and shrink away, at most 10 at a time.
+///     let emitter = Emitter::new(1000.0)
+///         .position([0.25, 0.25, 0.0])
+///         .gravity([0.0, 0.1, 0.0])
+///         .color(red, red)
+///         .size(0.5, 0.0)
+///         .max_particles(10);
+///     let mut sparks = Particles::new(square(&shader), emitter);
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         sparks.advance(&frame);
+///         assert!(sparks.len() <= 10);
+///         frame.draw_particles(&shader, &mut sparks);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(3, 3), red);
+/// assert_ne!(gpu.raster().pixel(0, 0), red);
+///
+/// // The same seed gives the same particles.
+/// let shader = Shader::new(builder());
+/// let emitter = Emitter::new(100.0)
+///     .velocity([-1.0, -1.0, 0.0], [1.0, 1.0, 0.0])
+///     .seed(42);
+/// let mut a = Particles::new(square(&shader), emitter);
+/// let mut b = Particles::new(square(&shader), emitter);
+/// for ms in [20, 50, 30] {
+///     a.advance_by(Duration::from_millis(ms));
+///     b.advance_by(Duration::from_millis(ms));
+/// }
+/// assert!(!a.is_empty());
+/// assert!(a.positions().eq(b.positions()));
+/// ```
+pub struct Particles {
+    emitter: Emitter,
+    instances: Instances,
+    pub(super) texture: Option<Arc<Texture>>,
+    particles: Vec<Particle>,
+    rng: WyRand,
+    // Part of a particle that's waiting to be spawned.
+    pending: f32,
+}
+
+impl Particles {
+    /// Create a particle system with no particles yet.
+    pub fn new(shape: Shape, emitter: Emitter) -> Self {
+        Particles {
+            instances: Instances::new(shape),
+            texture: None,
+            particles: Vec::new(),
+            rng: WyRand::new_seed(emitter.seed),
+            pending: 0.0,
+            emitter,
+        }
+    }
+
+    /// Get the number of particles alive.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Returns true if there are no particles alive.
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Get the positions of the particles alive, oldest first.
+    pub fn positions(&self) -> impl Iterator<Item = [f32; 3]> + '_ {
+        self.particles.iter().map(|particle| particle.position)
+    }
+
+    /// Move the emitter, for the particles spawned after.
+    pub fn set_position(&mut self, position: [f32; 3]) {
+        self.emitter.position = position;
+    }
+
+    /// Set the texture particles are drawn with, for shaders that are for
+    /// graphics (or `None` to draw without one).
+    pub fn set_texture(&mut self, texture: Option<Arc<Texture>>) {
+        self.texture = texture;
+    }
+
+    /// Get the texture particles are drawn with.
+    pub fn texture(&self) -> Option<&Texture> {
+        self.texture.as_deref()
+    }
+
+    /// Advance the particles by the time elapsed since the previous frame of
+    /// a `canvas`.
+    pub fn advance<C: Canvas>(&mut self, canvas: &C) {
+        self.advance_by(canvas.elapsed());
+    }
+
+    /// Advance the particles by `elapsed` time, spawning new ones and
+    /// removing the ones that died.
+    pub fn advance_by(&mut self, elapsed: Duration) {
+        let dt = elapsed.as_secs_f32();
+        let gravity = self.emitter.gravity;
+        self.particles.retain_mut(|particle| {
+            particle.age += dt;
+            let motion = particle.velocity.iter_mut().zip(gravity.iter());
+            for ((v, g), p) in motion.zip(particle.position.iter_mut()) {
+                *v += g * dt;
+                *p += *v * dt;
+            }
+            particle.age < particle.lifetime
+        });
+
+        self.pending += self.emitter.rate * dt;
+        while self.pending >= 1.0 {
+            if self.particles.len() >= self.emitter.max_particles {
+                // Don't spawn a burst once there's room again.
+                self.pending = self.pending.fract();
+                break;
+            }
+            self.pending -= 1.0;
+            self.spawn();
+        }
+
+        for (index, particle) in self.particles.iter().enumerate() {
+            let life = particle.age / particle.lifetime;
+            let (start, end) = self.emitter.sizes;
+            let size = start + (end - start) * life;
+            let [x, y, z] = particle.position;
+            let transform =
+                Transform::new().scale(size, size, size).translate(x, y, z);
+            if index < self.instances.len() {
+                self.instances.set(index, &transform);
+            } else {
+                self.instances.push(&transform);
+            }
+            match self.emitter.colors {
+                Some((start, end)) => {
+                    let mut color = [0; 4];
+                    for (i, c) in color.iter_mut().enumerate() {
+                        let (start, end) =
+                            (f32::from(start[i]), f32::from(end[i]));
+                        *c = (start + (end - start) * life).round() as u8;
+                    }
+                    let [r, g, b, a] = color;
+                    self.instances.set_tint(index, SRgba8::new(r, g, b, a));
+                }
+                None => self.instances.clear_tint(index),
+            }
+        }
+        self.instances.truncate(self.particles.len());
+    }
+
+    fn spawn(&mut self) {
+        let (min, max) = self.emitter.lifetime;
+        let lifetime = min.as_secs_f32()
+            + (max - min).as_secs_f32() * random(&mut self.rng);
+        let (min, max) = self.emitter.velocity;
+        let mut velocity = [0.0; 3];
+        for (i, v) in velocity.iter_mut().enumerate() {
+            *v = min[i] + (max[i] - min[i]) * random(&mut self.rng);
+        }
+        self.particles.push(Particle {
+            position: self.emitter.position,
+            velocity,
+            age: 0.0,
+            lifetime,
+        });
+    }
+
+    /// Send the particles to the GPU, and get the group to draw them with
+    /// (for drawing with [`Canvas::draw_with()`] or
+    /// [`Canvas::draw_graphic_with()`]).
+    ///
+    /// [`Canvas::draw_with()`]: super::Canvas::draw_with
+    /// [`Canvas::draw_graphic_with()`]: super::Canvas::draw_graphic_with
+    pub fn group(&mut self) -> &Group {
+        self.instances.group()
+    }
+}
+
+// Get a random number from 0 to 1.
+fn random(rng: &mut WyRand) -> f32 {
+    (u64::from_ne_bytes(rng.rand()) >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -0,0 +1,204 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{mat4, Group, Ray, ShapeBuilder, Transform};
+
+// The box around the vertices of a shape.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Bounds {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Bounds {
+    // Get the bounds of a shape, for a shader with vertices that are
+    // `stride` floats long (with a Z coordinate if `depth`).
+    pub(super) fn new(
+        builder: &ShapeBuilder,
+        depth: bool,
+        stride: usize,
+    ) -> Self {
+        let mut bounds = Bounds {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        };
+        let mut vertices: &[f32] = &[];
+        for face in builder.faces.iter() {
+            if let Some(ref v) = face.vertices {
+                vertices = v;
+            }
+            let transform = match face.transform {
+                Some(transform) => transform,
+                None => continue,
+            };
+            for v in vertices.chunks_exact(stride) {
+                let z = if depth { v[2] } else { 0.0 };
+                let position = transform * [v[0], v[1], z];
+                for (i, p) in position.iter().enumerate() {
+                    bounds.min[i] = bounds.min[i].min(*p);
+                    bounds.max[i] = bounds.max[i].max(*p);
+                }
+            }
+        }
+        bounds
+    }
+}
+
+// Invert the affine part of a transform (perspective is ignored), or `None`
+// if it squashes shapes flat.
+fn invert(transform: Transform) -> Option<[[f32; 4]; 3]> {
+    let m = mat4(transform);
+    // Rows of the 3x3 part (`Transform`s are column-major).
+    let a = [
+        [m[0][0], m[1][0], m[2][0]],
+        [m[0][1], m[1][1], m[2][1]],
+        [m[0][2], m[1][2], m[2][2]],
+    ];
+    // Transposed cofactors.
+    let adjugate = [
+        [
+            a[1][1] * a[2][2] - a[1][2] * a[2][1],
+            a[0][2] * a[2][1] - a[0][1] * a[2][2],
+            a[0][1] * a[1][2] - a[0][2] * a[1][1],
+        ],
+        [
+            a[1][2] * a[2][0] - a[1][0] * a[2][2],
+            a[0][0] * a[2][2] - a[0][2] * a[2][0],
+            a[0][2] * a[1][0] - a[0][0] * a[1][2],
+        ],
+        [
+            a[1][0] * a[2][1] - a[1][1] * a[2][0],
+            a[0][1] * a[2][0] - a[0][0] * a[2][1],
+            a[0][0] * a[1][1] - a[0][1] * a[1][0],
+        ],
+    ];
+    let det = a[0][0] * adjugate[0][0]
+        + a[0][1] * adjugate[1][0]
+        + a[0][2] * adjugate[2][0];
+    if det.abs() <= f32::EPSILON * f32::EPSILON {
+        return None;
+    }
+    // Rows of the inverse, with the inverse translation last.
+    let mut inverse = [[0.0; 4]; 3];
+    for (r, adjugate) in inverse.iter_mut().zip(adjugate.iter()) {
+        for col in 0..3 {
+            r[col] = adjugate[col] / det;
+        }
+        r[3] = -(0..3).map(|col| r[col] * m[3][col]).sum::<f32>();
+    }
+    Some(inverse)
+}
+
+// Apply an inverse transform to a point (or a direction, without `w`).
+fn apply(inverse: &[[f32; 4]; 3], v: [f32; 3], w: f32) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (o, row) in out.iter_mut().zip(inverse.iter()) {
+        *o = row[0] * v[0] + row[1] * v[1] + row[2] * v[2] + row[3] * w;
+    }
+    out
+}
+
+impl Group {
+    // Remember where a shape was written, for picking.
+    pub(super) fn record(
+        &mut self,
+        id: u32,
+        bounds: Bounds,
+        transform: &Transform,
+    ) {
+        let id = id as usize;
+        if id >= self.1.len() {
+            self.1.resize(id + 1, None);
+        }
+        self.1[id] = Some((bounds, *transform));
+    }
+
+    /// Get the id of the shape under a `point` on a canvas drawn without a
+    /// camera (like a pointer position from [`input`](crate::input), X from
+    /// 0 to 1 and Y from 0 to the canvas height), or `None` if there isn't
+    /// one.
+    ///
+    /// Shapes are hit anywhere in the box around their vertices.  If shapes
+    /// overlap, the one with the highest id (drawn last) is picked.
+    ///
+    /// ```rust
+    /// use cala::graphics::{Group, Ray, Shader, ShapeBuilder, Transform};
+    /// # use cala::graphics::doctest::builder;
+    ///
+    /// let shader = Shader::new(builder());
+    /// let square = ShapeBuilder::quad(&builder(), [1.0; 4]).finish(&shader);
+    /// let half = Transform::new().scale(0.5, 0.5, 1.0);
+    /// let mut group = Group::new();
+    /// group.write(0, &square, &half);
+    /// group.write(1, &square, &half.translate(0.25, 0.25, 0.0));
+    /// assert_eq!(group.pick([0.1, 0.1]), Some(0));
+    /// assert_eq!(group.pick([0.4, 0.4]), Some(1));
+    /// assert_eq!(group.pick([0.9, 0.1]), None);
+    ///
+    /// let ray = Ray {
+    ///     origin: [0.1, 0.1, 1.0],
+    ///     direction: [0.0, 0.0, -1.0],
+    /// };
+    /// assert_eq!(group.pick_ray(&ray), Some((0, 1.0)));
+    /// ```
+    pub fn pick(&self, point: [f32; 2]) -> Option<u32> {
+        let point = [point[0], point[1], 0.0];
+        self.1.iter().enumerate().rev().find_map(|(id, entry)| {
+            let (bounds, transform) = entry.as_ref()?;
+            let p = apply(&invert(*transform)?, point, 1.0);
+            let inside =
+                (0..2).all(|i| p[i] >= bounds.min[i] && p[i] <= bounds.max[i]);
+            if inside {
+                Some(id as u32)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Get the id of the nearest shape hit by a `ray` (from
+    /// [`Canvas::unproject()`](super::Canvas::unproject)), and the distance
+    /// along the ray to where it hit the box around the shape's vertices.
+    pub fn pick_ray(&self, ray: &Ray) -> Option<(u32, f32)> {
+        let mut nearest: Option<(u32, f32)> = None;
+        for (id, entry) in self.1.iter().enumerate() {
+            let (bounds, transform) = match entry {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let inverse = match invert(*transform) {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            // Distances are the same in the shape's space, because the
+            // direction isn't normalized again.
+            let origin = apply(&inverse, ray.origin, 1.0);
+            let direction = apply(&inverse, ray.direction, 0.0);
+            // Slab test, flat shapes included.
+            let (mut near, mut far) = (0.0f32, f32::INFINITY);
+            for i in 0..3 {
+                if direction[i].abs() <= f32::EPSILON {
+                    if origin[i] < bounds.min[i] || origin[i] > bounds.max[i] {
+                        far = -1.0;
+                    }
+                    continue;
+                }
+                let a = (bounds.min[i] - origin[i]) / direction[i];
+                let b = (bounds.max[i] - origin[i]) / direction[i];
+                near = near.max(a.min(b));
+                far = far.min(a.max(b));
+            }
+            if near <= far && nearest.map_or(true, |(_, d)| near < d) {
+                nearest = Some((id as u32, near));
+            }
+        }
+        nearest
+    }
+}
@@ -0,0 +1,135 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+/// How drawn colors are combined with the colors already on the canvas.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Blend {
+    /// Replace the old color.
+    Opaque,
+    /// Mix with the old color by alpha (for translucency).
+    Alpha,
+    /// Add to the old color, scaled by alpha (for glowing particles).
+    Additive,
+}
+
+/// Which faces of triangles aren't drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cull {
+    /// Draw both sides.
+    None,
+    /// Don't draw triangles facing away (clockwise on the canvas).
+    Back,
+    /// Don't draw triangles facing towards the camera.
+    Front,
+}
+
+/// Blending, depth testing and culling for a draw, with
+/// [`Canvas::draw_with()`](super::Canvas::draw_with) and
+/// [`Canvas::draw_graphic_with()`](super::Canvas::draw_graphic_with).
+///
+/// Depth testing only applies to shaders with depth.  Draws without a
+/// `Pipeline` use alpha blending if their shader blends, and the defaults
+/// otherwise.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Blend, Canvas, Cull, Group, Headless, Pipeline, Shader,
+///     ShapeBuilder, Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     let green = [0.0, 1.0, 0.0, 1.0];
+///     let green = ShapeBuilder::quad(&builder(), green).finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &green, &Transform::new().scale(0.5, 0.5, 1.0));
+///     // Glowing particles that don't hide each other.
+///     let glow = Pipeline::new()
+///         .blend(Blend::Additive)
+///         .depth_write(false)
+///         .cull(Cull::None);
+///     exec!({
+///         for _ in 0..2 {
+///             let mut frame = Frame::new(SRgb32::new(1.0, 0.0, 0.0)).await;
+///             frame.draw_with(&shader, &group, glow);
+///         }
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// for _ in 0..2 {
+///     gpu.run(std::time::Duration::from_millis(16));
+/// }
+/// assert_eq!(gpu.raster().pixel(1, 1), SRgba8::new(255, 255, 0, 255));
+/// assert_eq!(gpu.raster().pixel(6, 6), SRgba8::new(255, 0, 0, 255));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub(super) blend: Blend,
+    pub(super) depth_test: bool,
+    pub(super) depth_write: bool,
+    pub(super) cull: Cull,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    /// Create a `Pipeline` that's opaque, depth tested and written, and culls
+    /// back faces.
+    pub fn new() -> Self {
+        Pipeline {
+            blend: Blend::Opaque,
+            depth_test: true,
+            depth_write: true,
+            cull: Cull::Back,
+        }
+    }
+
+    /// Set how colors are blended (default: `Blend::Opaque`).
+    pub fn blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set whether pixels behind what's already drawn are skipped
+    /// (default: true).
+    pub fn depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    /// Set whether drawn pixels hide what's drawn behind them later
+    /// (default: true).
+    pub fn depth_write(mut self, depth_write: bool) -> Self {
+        self.depth_write = depth_write;
+        self
+    }
+
+    /// Set which faces are culled (default: `Cull::Back`).
+    pub fn cull(mut self, cull: Cull) -> Self {
+        self.cull = cull;
+        self
+    }
+
+    // Get the pipeline for a draw, defaulting to the shader's blending.
+    pub(super) fn or_shader(pipeline: Option<Self>, blend: bool) -> Self {
+        pipeline.unwrap_or_else(|| {
+            Self::new().blend(if blend { Blend::Alpha } else { Blend::Opaque })
+        })
+    }
+}
@@ -0,0 +1,570 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{gl, Cull, Id, Pipeline, ShaderBuilder, Texture, Transform};
+use pix::{el::Pixel, rgb::SRgba8, Raster};
+use std::sync::Arc;
+
+// Luma of a linear-ish color, for finding edges.
+const LUMA: [f32; 3] = [0.299, 0.587, 0.114];
+// Weights of the bloom blur taps, for each axis (they add up to 16).
+const BLUR: [f32; 5] = [1.0, 4.0, 6.0, 4.0, 1.0];
+// How much FXAA blurs along edges, and at least how much in dark areas.
+const FXAA_REDUCE_MUL: f32 = 1.0 / 8.0;
+const FXAA_REDUCE_MIN: f32 = 1.0 / 128.0;
+// The furthest (in pixels) FXAA looks along an edge.
+const FXAA_SPAN: f32 = 8.0;
+
+/// A full-screen effect, run over everything drawn in a frame with
+/// [`Frame::post_process()`](crate::window::Frame::post_process).
+#[derive(Clone)]
+pub enum Effect {
+    /// Make bright parts glow, by adding the parts of colors above
+    /// `threshold` (0 to 1) blurred over `radius` pixels, scaled by
+    /// `intensity`.
+    Bloom {
+        /// How bright colors have to be to glow.
+        threshold: f32,
+        /// How bright the glow is.
+        intensity: f32,
+        /// How far the glow spreads, in pixels.
+        radius: f32,
+    },
+    /// Darken towards the corners, by `strength` (0 to 1) at the corners.
+    Vignette {
+        /// How dark the corners are.
+        strength: f32,
+    },
+    /// Replace each color with the closest one in a lookup table.
+    ColorGrade(Arc<Lut>),
+    /// Smooth jagged edges, with fast approximate anti-aliasing.
+    Fxaa,
+    /// Darken every other band of `spacing` rows of pixels by `strength` (0
+    /// to 1), like a CRT.
+    Scanlines {
+        /// How dark the dark rows are.
+        strength: f32,
+        /// How many rows of pixels are in each band.
+        spacing: u32,
+    },
+}
+
+impl Effect {
+    // Get the pass to send to the draw thread.
+    pub(crate) fn pass(&self) -> Pass {
+        match *self {
+            Effect::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => Pass::Bloom(threshold, intensity, radius),
+            Effect::Vignette { strength } => Pass::Vignette(strength),
+            Effect::ColorGrade(ref lut) => {
+                Pass::ColorGrade(lut.texture.0, lut.size)
+            }
+            Effect::Fxaa => Pass::Fxaa,
+            Effect::Scanlines { strength, spacing } => {
+                Pass::Scanlines(strength, spacing.max(1))
+            }
+        }
+    }
+}
+
+/// A color lookup table for [`Effect::ColorGrade`], stored on the GPU.
+///
+/// Tables have `size` steps for each of red, green and blue, laid out as a
+/// strip of `size` squares that are `size` pixels wide: red goes left to
+/// right in each square, green top to bottom, and blue from one square to
+/// the next.
+pub struct Lut {
+    texture: Texture,
+    size: u32,
+}
+
+impl Lut {
+    /// Create a `Lut` from a strip of squares, which must be as wide as its
+    /// height squared.  Returns `None` if it isn't, or if it's less than 2
+    /// pixels high.
+    ///
+    /// ```rust
+    /// use cala::graphics::Lut;
+    /// use cala::video::{rgb::SRgba8, Raster};
+    ///
+    /// let strip = Raster::<SRgba8>::with_clear(4, 2);
+    /// assert_eq!(Lut::new(&strip).unwrap().size(), 2);
+    /// let square = Raster::<SRgba8>::with_clear(4, 4);
+    /// assert!(Lut::new(&square).is_none());
+    /// ```
+    pub fn new<P: Pixel>(raster: &Raster<P>) -> Option<Self>
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        let size = raster.height();
+        if size < 2 || raster.width() != size * size {
+            return None;
+        }
+        Some(Lut {
+            texture: Texture::new(raster),
+            size,
+        })
+    }
+
+    /// Create a `Lut` with `size` steps for each channel, from a function
+    /// that maps colors (with channels from 0 to 1).
+    ///
+    /// ```rust
+    /// use cala::graphics::Lut;
+    ///
+    /// // Shades of gray.
+    /// let gray = Lut::from_fn(16, |[r, g, b]| {
+    ///     let gray = 0.299 * r + 0.587 * g + 0.114 * b;
+    ///     [gray, gray, gray]
+    /// });
+    /// assert_eq!(gray.size(), 16);
+    /// ```
+    ///
+    /// # Panics
+    /// If `size` is less than 2.
+    pub fn from_fn<F>(size: u32, f: F) -> Self
+    where
+        F: Fn([f32; 3]) -> [f32; 3],
+    {
+        assert!(size > 1);
+        let step = |i: u32| i as f32 / (size - 1) as f32;
+        let mut pixels = Vec::with_capacity((size * size * size) as usize * 4);
+        for g in 0..size {
+            for b in 0..size {
+                for r in 0..size {
+                    for c in f([step(r), step(g), step(b)]).iter() {
+                        pixels.push(to_u8(*c));
+                    }
+                    pixels.push(255);
+                }
+            }
+        }
+        let raster =
+            Raster::<SRgba8>::with_u8_buffer(size * size, size, pixels);
+        Lut {
+            texture: Texture::new(&raster),
+            size,
+        }
+    }
+
+    /// Get the number of steps for each channel.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+// An effect for the draw thread, with the settings its shader needs.
+#[derive(Clone, Debug)]
+pub(crate) enum Pass {
+    // Threshold, intensity and radius.
+    Bloom(f32, f32, f32),
+    // Strength.
+    Vignette(f32),
+    // Lookup table texture and size.
+    ColorGrade(Id, u32),
+    Fxaa,
+    // Strength and spacing.
+    Scanlines(f32, u32),
+}
+
+impl Pass {
+    // Get which shader runs this pass (an index into `FRAGS`).
+    fn kind(&self) -> usize {
+        match self {
+            Pass::Bloom(..) => 0,
+            Pass::Vignette(..) => 1,
+            Pass::ColorGrade(..) => 2,
+            Pass::Fxaa => 3,
+            Pass::Scanlines(..) => 4,
+        }
+    }
+
+    // Get the `params` uniform of the pass's shader.
+    fn params(&self) -> [f32; 4] {
+        match *self {
+            Pass::Bloom(threshold, intensity, radius) => {
+                [threshold, intensity, radius, 0.0]
+            }
+            Pass::Vignette(strength) => [strength, 0.0, 0.0, 0.0],
+            Pass::ColorGrade(_, size) => [size as f32, 0.0, 0.0, 0.0],
+            Pass::Fxaa => [0.0; 4],
+            Pass::Scanlines(strength, spacing) => {
+                [strength, spacing as f32, 0.0, 0.0]
+            }
+        }
+    }
+}
+
+fn to_u8(channel: f32) -> u8 {
+    (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn luma(color: [f32; 3]) -> f32 {
+    (0..3).map(|i| color[i] * LUMA[i]).sum()
+}
+
+// A copy of the screen to sample while running an effect on the CPU.
+struct Image {
+    pixels: Vec<[f32; 4]>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    fn new(raster: &Raster<SRgba8>) -> Self {
+        let pixels = raster
+            .as_u8_slice()
+            .chunks_exact(4)
+            .map(|p| {
+                let c = |i: usize| f32::from(p[i]) / 255.0;
+                [c(0), c(1), c(2), c(3)]
+            })
+            .collect();
+        Image {
+            pixels,
+            width: raster.width() as usize,
+            height: raster.height() as usize,
+        }
+    }
+
+    // Get a pixel, clamped to the edges.
+    fn texel(&self, x: isize, y: isize) -> [f32; 4] {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // Sample at a point in pixels with linear filtering, like the shaders
+    // (pixel centers are at half pixels).
+    fn sample(&self, x: f32, y: f32) -> [f32; 3] {
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (left, top) = (x.floor(), y.floor());
+        let (tx, ty) = (x - left, y - top);
+        let (left, top) = (left as isize, top as isize);
+        let mut color = [0.0; 3];
+        for (dy, wy) in [(0, 1.0 - ty), (1, ty)] {
+            for (dx, wx) in [(0, 1.0 - tx), (1, tx)] {
+                let texel = self.texel(left + dx, top + dy);
+                for (c, t) in color.iter_mut().zip(texel.iter()) {
+                    *c += t * wx * wy;
+                }
+            }
+        }
+        color
+    }
+}
+
+// Run a pass on the CPU the same way as its shader, with its lookup table if
+// it has one.
+pub(super) fn apply(
+    raster: &mut Raster<SRgba8>,
+    pass: &Pass,
+    lut: Option<&Raster<SRgba8>>,
+) {
+    let image = Image::new(raster);
+    let (width, height) = (image.width, image.height);
+    let pixels = raster.as_u8_slice_mut().chunks_exact_mut(4);
+    for (i, pixel) in pixels.enumerate() {
+        let (x, y) = (i % width, i / width);
+        let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+        let [r, g, b, _a] = image.pixels[i];
+        let color = [r, g, b];
+        let color = match *pass {
+            Pass::Bloom(threshold, intensity, radius) => {
+                let mut glow = [0.0; 3];
+                for (j, wy) in BLUR.iter().enumerate() {
+                    for (i, wx) in BLUR.iter().enumerate() {
+                        let dx = (i as f32 - 2.0) * radius * 0.5;
+                        let dy = (j as f32 - 2.0) * radius * 0.5;
+                        let tap = image.sample(cx + dx, cy + dy);
+                        for (g, t) in glow.iter_mut().zip(tap.iter()) {
+                            *g += (t - threshold).max(0.0) * wx * wy;
+                        }
+                    }
+                }
+                let mut color = color;
+                for (c, g) in color.iter_mut().zip(glow.iter()) {
+                    *c += intensity * g / 256.0;
+                }
+                color
+            }
+            Pass::Vignette(strength) => {
+                let u = cx / width as f32 - 0.5;
+                let v = cy / height as f32 - 0.5;
+                let factor = (1.0 - strength * 2.0 * (u * u + v * v)).max(0.0);
+                color.map(|c| c * factor)
+            }
+            Pass::ColorGrade(_, size) => match lut {
+                Some(lut) => {
+                    let step = |c: f32| {
+                        (c.clamp(0.0, 1.0) * (size - 1) as f32).round() as u32
+                    };
+                    let [r, g, b] = color.map(step);
+                    let index = (g * size * size + b * size + r) as usize * 4;
+                    let p = &lut.as_u8_slice()[index..index + 3];
+                    [p[0], p[1], p[2]].map(|c| f32::from(c) / 255.0)
+                }
+                None => color,
+            },
+            Pass::Fxaa => fxaa(&image, color, cx, cy),
+            Pass::Scanlines(strength, spacing) => {
+                if (y as u32 / spacing) % 2 == 1 {
+                    color.map(|c| c * (1.0 - strength))
+                } else {
+                    color
+                }
+            }
+        };
+        for (p, c) in pixel.iter_mut().zip(color.iter()) {
+            *p = to_u8(*c);
+        }
+    }
+}
+
+// FXAA for one pixel (centered at `cx`, `cy`) with color `m`.
+fn fxaa(image: &Image, m: [f32; 3], cx: f32, cy: f32) -> [f32; 3] {
+    let nw = luma(image.sample(cx - 1.0, cy - 1.0));
+    let ne = luma(image.sample(cx + 1.0, cy - 1.0));
+    let sw = luma(image.sample(cx - 1.0, cy + 1.0));
+    let se = luma(image.sample(cx + 1.0, cy + 1.0));
+    let lm = luma(m);
+    let low = lm.min(nw.min(ne).min(sw.min(se)));
+    let high = lm.max(nw.max(ne).max(sw.max(se)));
+    let dir = [-((nw + ne) - (sw + se)), (nw + sw) - (ne + se)];
+    let reduce =
+        ((nw + ne + sw + se) * 0.25 * FXAA_REDUCE_MUL).max(FXAA_REDUCE_MIN);
+    let scale = 1.0 / (dir[0].abs().min(dir[1].abs()) + reduce);
+    let dir = dir.map(|d| (d * scale).clamp(-FXAA_SPAN, FXAA_SPAN));
+    let tap = |t: f32| image.sample(cx + dir[0] * t, cy + dir[1] * t);
+    let (a0, a1) = (tap(1.0 / 3.0 - 0.5), tap(2.0 / 3.0 - 0.5));
+    let (b0, b1) = (tap(-0.5), tap(0.5));
+    let mut a = [0.0; 3];
+    let mut b = [0.0; 3];
+    for i in 0..3 {
+        a[i] = 0.5 * (a0[i] + a1[i]);
+        b[i] = a[i] * 0.5 + 0.25 * (b0[i] + b1[i]);
+    }
+    let lb = luma(b);
+    if lb < low || lb > high {
+        a
+    } else {
+        b
+    }
+}
+
+// Vertex shader for all effects, which draw a quad over the screen.
+const VERT: &str = "uniform mat4 cam;
+attribute vec2 pos;
+attribute vec2 texpos;
+varying vec2 uv;
+void main() {
+    uv = texpos;
+    gl_Position = cam * vec4(pos, 0.0, 1.0);
+}\0";
+
+// Fragment shader source for an effect, with the highest precision the GPU
+// has, and the uniforms set by `gl::effect_uniforms()`.
+macro_rules! frag {
+    ($body:literal) => {
+        concat!(
+            "#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+uniform sampler2D screen;
+uniform vec4 params;
+uniform vec2 size;
+varying vec2 uv;
+",
+            $body,
+            "\0"
+        )
+    };
+}
+
+// Fragment shaders for each kind of pass (see `Pass::kind()`).
+const FRAGS: [&str; 5] = [
+    frag!(
+        "float weight(float i) {
+    return i == 2.0 ? 6.0 : (i == 1.0 || i == 3.0 ? 4.0 : 1.0);
+}
+void main() {
+    vec4 color = texture2D(screen, uv);
+    vec3 glow = vec3(0.0);
+    for (int j = 0; j < 5; j++) {
+        for (int i = 0; i < 5; i++) {
+            vec2 offset = vec2(float(i) - 2.0, float(j) - 2.0) * params.z * 0.5;
+            vec3 tap = texture2D(screen, uv + offset / size).rgb;
+            glow += max(tap - params.x, 0.0) * weight(float(i))
+                * weight(float(j));
+        }
+    }
+    gl_FragColor = vec4(color.rgb + params.y * glow / 256.0, color.a);
+}"
+    ),
+    frag!(
+        "void main() {
+    vec4 color = texture2D(screen, uv);
+    vec2 d = uv - 0.5;
+    float factor = max(1.0 - params.x * 2.0 * dot(d, d), 0.0);
+    gl_FragColor = vec4(color.rgb * factor, color.a);
+}"
+    ),
+    frag!(
+        "uniform sampler2D lut;
+void main() {
+    vec4 color = texture2D(screen, uv);
+    float n = params.x;
+    vec3 i = floor(clamp(color.rgb, 0.0, 1.0) * (n - 1.0) + 0.5);
+    vec2 texel = vec2((i.b * n + i.r + 0.5) / (n * n), (i.g + 0.5) / n);
+    gl_FragColor = vec4(texture2D(lut, texel).rgb, color.a);
+}"
+    ),
+    frag!(
+        "float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+vec3 tap(vec2 offset) {
+    return texture2D(screen, uv + offset / size).rgb;
+}
+void main() {
+    vec4 color = texture2D(screen, uv);
+    float nw = luma(tap(vec2(-1.0, -1.0)));
+    float ne = luma(tap(vec2(1.0, -1.0)));
+    float sw = luma(tap(vec2(-1.0, 1.0)));
+    float se = luma(tap(vec2(1.0, 1.0)));
+    float m = luma(color.rgb);
+    float low = min(m, min(min(nw, ne), min(sw, se)));
+    float high = max(m, max(max(nw, ne), max(sw, se)));
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float reduce = max((nw + ne + sw + se) * 0.25 / 8.0, 1.0 / 128.0);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, -8.0, 8.0);
+    vec3 a = 0.5 * (tap(dir * (1.0 / 3.0 - 0.5))
+        + tap(dir * (2.0 / 3.0 - 0.5)));
+    vec3 b = a * 0.5 + 0.25 * (tap(dir * -0.5) + tap(dir * 0.5));
+    float lb = luma(b);
+    gl_FragColor = vec4((lb < low || lb > high) ? a : b, color.a);
+}"
+    ),
+    frag!(
+        "void main() {
+    vec4 color = texture2D(screen, uv);
+    float row = floor((size.y - gl_FragCoord.y) / params.y);
+    float dark = mod(row, 2.0);
+    gl_FragColor = vec4(color.rgb * (1.0 - params.x * dark), color.a);
+}"
+    ),
+];
+
+// A copy of the screen, with the shaders and quad that run effects on it
+// (only used by the draw thread).
+#[derive(Default)]
+pub(super) struct Screen {
+    // Shader and GL program name for each kind of pass.
+    shaders: [Option<(window::Shader, u32)>; 5],
+    // A quad over the screen, and the aspect ratio it was made for.
+    quad: Option<(window::Group, f32)>,
+    // The copy of the screen, its GL texture name, width and height.
+    copy: Option<(window::RasterId, u32, [i32; 2])>,
+}
+
+impl Screen {
+    // Run passes over what's been drawn on the window, with the GL texture
+    // name of each pass's lookup table.
+    pub(super) fn run(
+        &mut self,
+        window: &mut window::Window,
+        passes: &[Pass],
+        luts: &[Option<u32>],
+    ) {
+        if passes.is_empty() {
+            return;
+        }
+        let [_x, _y, width, height] = gl::viewport();
+        if self.copy.as_ref().map(|copy| copy.2) != Some([width, height]) {
+            if let Some((_raster, name, _size)) = self.copy.take() {
+                gl::delete_texture(name);
+            }
+            let pixels = vec![0; width as usize * height as usize * 4];
+            let raster =
+                window.graphic(&pixels, width as usize, height as usize);
+            // The new texture is left bound.
+            let name = gl::texture_binding();
+            gl::smooth_texture();
+            self.copy = Some((raster, name, [width, height]));
+        }
+        for pass in passes.iter() {
+            let kind = pass.kind();
+            if self.shaders[kind].is_none() {
+                let shader = window.shader_new(ShaderBuilder {
+                    tint: false,
+                    gradient: false,
+                    graphic: true,
+                    depth: false,
+                    blend: false,
+                    opengl_frag: FRAGS[kind],
+                    opengl_vert: VERT,
+                });
+                // The new program is left in use.
+                self.shaders[kind] = Some((shader, gl::current_program()));
+            }
+        }
+        // Canvas coordinates with the screen's aspect ratio, and texture
+        // coordinates from bottom to top like OpenGL's rows.
+        let aspect = window.aspect();
+        if self.quad.as_ref().map(|quad| quad.1) != Some(aspect) {
+            let (shader, _name) =
+                self.shaders[passes[0].kind()].as_mut().unwrap();
+            #[rustfmt::skip]
+            let shape = window::ShapeBuilder::new(shader)
+                .vert(&[
+                    0.0, 0.0, 0.0, 1.0,
+                    0.0, 1.0, 0.0, 0.0,
+                    1.0, 0.0, 1.0, 1.0,
+                    1.0, 0.0, 1.0, 1.0,
+                    0.0, 1.0, 0.0, 0.0,
+                    1.0, 1.0, 1.0, 0.0,
+                ])
+                .face(Transform::new())
+                .finish();
+            let mut group = window.group_new();
+            let transform = Transform::new().scale(1.0, aspect, 1.0);
+            group.write((0, 0), &shape, &transform);
+            self.quad = Some((group, aspect));
+        }
+
+        let (raster, name, _size) = self.copy.as_ref().unwrap();
+        let (quad, _aspect) = self.quad.as_ref().unwrap();
+        window.camera(Transform::new());
+        gl::pipeline(
+            &Pipeline::new()
+                .depth_test(false)
+                .depth_write(false)
+                .cull(Cull::None),
+        );
+        for (pass, lut) in passes.iter().zip(luts.iter()) {
+            let (shader, program) = self.shaders[pass.kind()].as_ref().unwrap();
+            gl::copy_screen(*name);
+            if let Some(lut) = lut {
+                gl::bind_lut(*lut);
+            }
+            let size = [width as f32, height as f32];
+            gl::effect_uniforms(*program, pass.params(), size);
+            // Binds the copy (again) for the `window` crate to keep track of.
+            window.draw_graphic(shader, quad, raster);
+        }
+        gl::depth_write();
+    }
+}
@@ -0,0 +1,204 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::Internal;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A kind of GPU resource.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// A [`Texture`](super::Texture).
+    Texture,
+    /// A [`Shader`](super::Shader).
+    Shader,
+    /// A [`Shape`](super::Shape).
+    Shape,
+    /// A [`Group`](super::Group).
+    Group,
+}
+
+/// A command that used a GPU resource after it was dropped (or before it was
+/// created), which was skipped instead of drawing with whatever re-used its
+/// slot.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, resource_errors, Canvas, Group, Headless, Player,
+///     Resource, Shader,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     exec!({
+///         // Groups are dropped at the end of each frame, and their slots
+///         // are re-used by the next one.
+///         let group = Group::new();
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// for _ in 0..3 {
+///     gpu.run(std::time::Duration::from_millis(16));
+/// }
+/// assert!(resource_errors().is_empty());
+///
+/// // Record a frame without the commands that created the shader.
+/// let path = std::env::temp_dir().join("cala-resource-error.bin");
+/// cala::graphics::record(&path).unwrap();
+/// gpu.run(std::time::Duration::from_millis(16));
+/// cala::graphics::stop_recording().unwrap();
+///
+/// let mut replayed = Headless::new(8, 8);
+/// let mut player = Player::open(&path).unwrap();
+/// while replayed.replay(&mut player).unwrap() {}
+/// let errors = resource_errors();
+/// assert_eq!(errors[0].resource, Resource::Shader);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResourceError {
+    /// The kind of resource that was used.
+    pub resource: Resource,
+    /// The slot the resource was in.
+    pub index: u32,
+}
+
+impl Display for ResourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Use of dropped {:?} {}", self.resource, self.index)
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+/// Take the commands that were skipped since the last call, because they used
+/// a GPU resource after it was dropped.
+pub fn resource_errors() -> Vec<ResourceError> {
+    let internal = Internal::new_lazy();
+    std::mem::take(&mut *internal.resource_errors.lock().unwrap())
+}
+
+// Record a skipped command.
+pub(super) fn report(error: ResourceError) {
+    let internal = Internal::new_lazy();
+    internal.resource_errors.lock().unwrap().push(error);
+}
+
+// A handle to a GPU resource: its slot, and how many resources were in that
+// slot before it, so that handles to dropped resources don't match.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Id {
+    pub(super) index: u32,
+    pub(super) generation: u32,
+}
+
+// Shown as the slot, then the generation if the slot was re-used (in trace
+// dumps).
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.generation {
+            0 => write!(f, "{}", self.index),
+            generation => write!(f, "{}v{}", self.index, generation),
+        }
+    }
+}
+
+// Hands out ids for one kind of resource, re-using the slots of dropped ones.
+#[derive(Default)]
+pub(super) struct Ids {
+    // The current generation of each slot.
+    generations: Vec<u32>,
+    free: Vec<u32>,
+}
+
+impl Ids {
+    pub(super) fn alloc(&mut self) -> Id {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.generations.len() as u32 - 1
+            }
+        };
+        Id {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    pub(super) fn free(&mut self, id: Id) {
+        let generation = &mut self.generations[id.index as usize];
+        *generation = generation.wrapping_add(1);
+        self.free.push(id.index);
+    }
+}
+
+// GPU resources of one kind, by id.
+pub(super) struct Slots<T> {
+    resource: Resource,
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Slots<T> {
+    pub(super) fn new(resource: Resource) -> Self {
+        Slots {
+            resource,
+            slots: Vec::new(),
+        }
+    }
+
+    fn error(&self, id: Id) -> ResourceError {
+        ResourceError {
+            resource: self.resource,
+            index: id.index,
+        }
+    }
+
+    // Store a resource at `id`, replacing the one it had (when reloading).
+    pub(super) fn store(&mut self, id: Id, item: T) {
+        let index = id.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index] = Some((id.generation, item));
+    }
+
+    // Get a resource that hasn't been dropped.
+    pub(super) fn get(&self, id: Id) -> Result<&T, ResourceError> {
+        match self.slots.get(id.index as usize) {
+            Some(Some((generation, item))) if *generation == id.generation => {
+                Ok(item)
+            }
+            _ => Err(self.error(id)),
+        }
+    }
+
+    // Get a resource that hasn't been dropped, mutably.
+    pub(super) fn get_mut(&mut self, id: Id) -> Result<&mut T, ResourceError> {
+        let error = self.error(id);
+        match self.slots.get_mut(id.index as usize) {
+            Some(Some((generation, item))) if *generation == id.generation => {
+                Ok(item)
+            }
+            _ => Err(error),
+        }
+    }
+
+    // Remove a resource, if it hasn't been already.
+    pub(super) fn remove(&mut self, id: Id) -> Result<T, ResourceError> {
+        self.get(id)?;
+        let (_generation, item) = self.slots[id.index as usize].take().unwrap();
+        Ok(item)
+    }
+}
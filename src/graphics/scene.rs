@@ -0,0 +1,332 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{registry::Ids, GpuCmd, Group, Id, Internal, Shape, Transform};
+use std::sync::Arc;
+
+/// A node in a [`Scene`].
+///
+/// Handles to removed nodes aren't re-used by new nodes, so using one panics
+/// instead of changing another node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SceneNode(Id);
+
+struct Node {
+    parent: Option<SceneNode>,
+    children: Vec<SceneNode>,
+    local: Transform,
+    // Local transform combined with the parents' (as of the last write).
+    world: Transform,
+    // The group slot with the node's shape.
+    slot: Option<u32>,
+    // Whether the local transform changed since the node was last written.
+    dirty: bool,
+}
+
+/// A tree of nodes with transforms relative to their parent, drawn with
+/// [`Canvas::draw_scene()`](super::Canvas::draw_scene).
+///
+/// Each node can have a [`Shape`], drawn with the node's transform combined
+/// with the transforms of all its parents, so moving a node moves everything
+/// under it.  Only nodes that moved since they were last drawn are sent to
+/// the GPU.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Headless, Scene, Shader, ShapeBuilder,
+///     Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+/// use std::sync::Arc;
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     let square = ShapeBuilder::quad(&builder(), [1.0; 4]).finish(&shader);
+///     let square = Arc::new(square);
+///     // A half-size square, with another one to the right of it.
+///     let mut scene = Scene::new();
+///     let half = Transform::new().scale(0.5, 0.5, 1.0);
+///     let body = scene.add(None, &half);
+///     scene.set_shape(body, Some(square.clone()));
+///     let right = Transform::new().translate(1.0, 0.0, 0.0);
+///     let arm = scene.add(Some(body), &right);
+///     scene.set_shape(arm, Some(square));
+///     let mut frames = 0;
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         // Move both squares down in the second frame.
+///         frames += 1;
+///         if frames == 2 {
+///             let down = half.translate(0.0, 0.5, 0.0);
+///             scene.set_transform(body, &down);
+///         }
+///         frame.draw_scene(&shader, &mut scene);
+///     });
+/// });
+///
+/// let white = SRgba8::new(255, 255, 255, 255);
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(1, 1), white);
+/// assert_eq!(gpu.raster().pixel(6, 1), white);
+/// assert_ne!(gpu.raster().pixel(1, 6), white);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_ne!(gpu.raster().pixel(6, 1), white);
+/// assert_eq!(gpu.raster().pixel(1, 6), white);
+/// assert_eq!(gpu.raster().pixel(6, 6), white);
+/// ```
+pub struct Scene {
+    group: Group,
+    ids: Ids,
+    nodes: Vec<Option<(u32, Node)>>,
+    // Nodes without a parent.
+    roots: Vec<SceneNode>,
+    // The shape in each group slot, and the node using it.  Slots keep their
+    // shape after the node is removed, so they can be hidden, and re-used by
+    // nodes with the same shape without moving the slots after them.
+    slots: Vec<(Arc<Shape>, Option<SceneNode>)>,
+    // Slots that need to be hidden.
+    hidden: Vec<u32>,
+    // Whether any node changed since the scene was last written.
+    changed: bool,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+impl Scene {
+    /// Create an empty scene.
+    pub fn new() -> Self {
+        Scene {
+            group: Group::new(),
+            ids: Ids::default(),
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            slots: Vec::new(),
+            hidden: Vec::new(),
+            changed: false,
+        }
+    }
+
+    fn node(&self, node: SceneNode) -> &Node {
+        match self.nodes.get(node.0.index as usize) {
+            Some(Some((generation, n))) if *generation == node.0.generation => {
+                n
+            }
+            _ => panic!("Use of removed scene node"),
+        }
+    }
+
+    fn node_mut(&mut self, node: SceneNode) -> &mut Node {
+        match self.nodes.get_mut(node.0.index as usize) {
+            Some(Some((generation, n))) if *generation == node.0.generation => {
+                n
+            }
+            _ => panic!("Use of removed scene node"),
+        }
+    }
+
+    // Get the list of children of a parent, or the roots.
+    fn siblings(&mut self, parent: Option<SceneNode>) -> &mut Vec<SceneNode> {
+        match parent {
+            Some(parent) => &mut self.node_mut(parent).children,
+            None => &mut self.roots,
+        }
+    }
+
+    /// Add a node under a `parent` (or at the root), with a `transform`
+    /// relative to it.
+    pub fn add(
+        &mut self,
+        parent: Option<SceneNode>,
+        transform: &Transform,
+    ) -> SceneNode {
+        let node = SceneNode(self.ids.alloc());
+        self.siblings(parent).push(node);
+        let index = node.0.index as usize;
+        if index >= self.nodes.len() {
+            self.nodes.resize_with(index + 1, || None);
+        }
+        self.nodes[index] = Some((
+            node.0.generation,
+            Node {
+                parent,
+                children: Vec::new(),
+                local: *transform,
+                world: *transform,
+                slot: None,
+                dirty: true,
+            },
+        ));
+        self.changed = true;
+        node
+    }
+
+    /// Remove a node, and all the nodes under it.
+    pub fn remove(&mut self, node: SceneNode) {
+        let parent = self.node(node).parent;
+        self.siblings(parent).retain(|child| *child != node);
+        let mut removed = vec![node];
+        while let Some(node) = removed.pop() {
+            self.set_shape(node, None);
+            let (_generation, n) =
+                self.nodes[node.0.index as usize].take().unwrap();
+            removed.extend(n.children);
+            self.ids.free(node.0);
+        }
+    }
+
+    /// Set the shape drawn at a node, or stop drawing one with `None`.
+    pub fn set_shape(&mut self, node: SceneNode, shape: Option<Arc<Shape>>) {
+        if let Some(slot) = self.node_mut(node).slot.take() {
+            self.slots[slot as usize].1 = None;
+            self.hidden.push(slot);
+            self.changed = true;
+        }
+        let shape = match shape {
+            Some(shape) => shape,
+            None => return,
+        };
+        let free = self
+            .slots
+            .iter()
+            .position(|(s, user)| user.is_none() && Arc::ptr_eq(s, &shape));
+        let slot = match free {
+            Some(slot) => {
+                self.slots[slot].1 = Some(node);
+                slot
+            }
+            None => {
+                self.slots.push((shape, Some(node)));
+                self.slots.len() - 1
+            }
+        };
+        let n = self.node_mut(node);
+        n.slot = Some(slot as u32);
+        n.dirty = true;
+        self.changed = true;
+    }
+
+    /// Set the transform of a node, relative to its parent.
+    pub fn set_transform(&mut self, node: SceneNode, transform: &Transform) {
+        let n = self.node_mut(node);
+        n.local = *transform;
+        n.dirty = true;
+        self.changed = true;
+    }
+
+    /// Get the transform of a node, relative to its parent.
+    pub fn transform(&self, node: SceneNode) -> Transform {
+        self.node(node).local
+    }
+
+    /// Get the transform of a node combined with the transforms of all its
+    /// parents.
+    pub fn world_transform(&self, node: SceneNode) -> Transform {
+        let n = self.node(node);
+        match n.parent {
+            Some(parent) => n.local * self.world_transform(parent),
+            None => n.local,
+        }
+    }
+
+    /// Move a node (with the nodes under it) under another `parent`, or to
+    /// the root.  Panics if `parent` is under `node`.
+    pub fn set_parent(&mut self, node: SceneNode, parent: Option<SceneNode>) {
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            assert_ne!(a, node, "Can't move a scene node under itself");
+            ancestor = self.node(a).parent;
+        }
+        let old = self.node(node).parent;
+        self.siblings(old).retain(|child| *child != node);
+        self.siblings(parent).push(node);
+        let n = self.node_mut(node);
+        n.parent = parent;
+        n.dirty = true;
+        self.changed = true;
+    }
+
+    /// Get the parent of a node (`None` at the root).
+    pub fn parent(&self, node: SceneNode) -> Option<SceneNode> {
+        self.node(node).parent
+    }
+
+    /// Get the nodes directly under a node.
+    pub fn children(&self, node: SceneNode) -> &[SceneNode] {
+        &self.node(node).children
+    }
+
+    /// Send the nodes that moved to the GPU, and get the group to draw them
+    /// with (for drawing with [`Canvas::draw_with()`] or
+    /// [`Canvas::draw_graphic()`]).
+    ///
+    /// [`Canvas::draw_with()`]: super::Canvas::draw_with
+    /// [`Canvas::draw_graphic()`]: super::Canvas::draw_graphic
+    pub fn group(&mut self) -> &Group {
+        if !self.changed {
+            return &self.group;
+        }
+        self.changed = false;
+        let mut writes = Vec::new();
+        // Hide slots by shrinking them away.
+        let hidden = Transform::new().scale(0.0, 0.0, 0.0);
+        for slot in self.hidden.drain(..) {
+            // Unless they were re-used.
+            if self.slots[slot as usize].1.is_none() {
+                writes.push((slot, hidden));
+            }
+        }
+        // Update world transforms from the roots down.
+        let mut stack: Vec<_> =
+            self.roots.iter().map(|root| (*root, None, false)).collect();
+        while let Some((node, parent, moved)) = stack.pop() {
+            let n = self.node_mut(node);
+            let moved = moved || n.dirty;
+            if moved {
+                n.world = match parent {
+                    Some(parent) => n.local * parent,
+                    None => n.local,
+                };
+                n.dirty = false;
+                if let Some(slot) = n.slot {
+                    writes.push((slot, n.world));
+                }
+            }
+            let world = n.world;
+            for child in n.children.iter() {
+                stack.push((*child, Some(world), moved));
+            }
+        }
+        // New slots go after the ones before them, so write them in order.
+        writes.sort_by_key(|(slot, _)| *slot);
+        if !writes.is_empty() {
+            let internal = Internal::new_lazy();
+            let mut cmds = internal.cmds.lock().unwrap();
+            for (slot, transform) in writes {
+                let shape = &self.slots[slot as usize].0;
+                self.group.record(slot, shape.1, &transform);
+                cmds.push(GpuCmd::GroupWrite(
+                    self.group.0,
+                    slot,
+                    shape.0,
+                    transform,
+                ));
+            }
+        }
+        &self.group
+    }
+}
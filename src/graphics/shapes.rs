@@ -0,0 +1,327 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{Mesh, ShaderBuilder, ShapeBuilder};
+use std::f32::consts::PI;
+
+// Triangles with texture coordinates, before they're laid out for a shader.
+#[derive(Default)]
+struct Triangles {
+    positions: Vec<[f32; 3]>,
+    texcoords: Vec<[f32; 2]>,
+}
+
+impl Triangles {
+    fn push(&mut self, position: [f32; 3], texcoord: [f32; 2]) {
+        self.positions.push(position);
+        self.texcoords.push(texcoord);
+    }
+
+    fn finish(self, shader: &ShaderBuilder, color: [f32; 4]) -> ShapeBuilder {
+        let mesh = Mesh {
+            colors: vec![color; self.positions.len()],
+            positions: self.positions,
+            texcoords: self.texcoords,
+            texture: None,
+        };
+        mesh.builder(shader)
+    }
+}
+
+// Fill a convex outline on the canvas (going clockwise, with Y down) with
+// triangles from its center, and texture coordinates across `size`.
+fn fan(outline: &[[f32; 2]], size: [f32; 2]) -> Triangles {
+    let mut center = [0.0; 2];
+    for point in outline {
+        center[0] += point[0] / outline.len() as f32;
+        center[1] += point[1] / outline.len() as f32;
+    }
+    let vertex =
+        |p: [f32; 2]| ([p[0], p[1], 0.0], [p[0] / size[0], p[1] / size[1]]);
+    let mut triangles = Triangles::default();
+    for (i, point) in outline.iter().enumerate() {
+        let next = outline[(i + 1) % outline.len()];
+        // Counter-clockwise on screen, so they face the canvas.
+        for p in [center, next, *point] {
+            let (position, texcoord) = vertex(p);
+            triangles.push(position, texcoord);
+        }
+    }
+    triangles
+}
+
+// Turn a profile of (radius, Y, texture V) points from top to bottom around
+// the Y axis in `segments` slices, facing outwards.
+fn lathe(profile: &[(f32, f32, f32)], segments: u32) -> Triangles {
+    let point = |i: u32, (r, y, v): (f32, f32, f32)| {
+        let u = i as f32 / segments as f32;
+        let angle = u * 2.0 * PI;
+        ([r * angle.sin(), y, r * angle.cos()], [u, v])
+    };
+    let mut triangles = Triangles::default();
+    for i in 0..segments {
+        for rows in profile.windows(2) {
+            let (top, bottom) = (rows[0], rows[1]);
+            let a = point(i, top);
+            let b = point(i, bottom);
+            let c = point(i + 1, bottom);
+            let d = point(i + 1, top);
+            // Skip the triangles that are squashed flat at the poles.
+            if bottom.0 != 0.0 {
+                for (position, texcoord) in [a, b, c] {
+                    triangles.push(position, texcoord);
+                }
+            }
+            if top.0 != 0.0 {
+                for (position, texcoord) in [a, c, d] {
+                    triangles.push(position, texcoord);
+                }
+            }
+        }
+    }
+    triangles
+}
+
+/// Generated shapes, with vertices in the layout of a shader (see
+/// [`Mesh::builder()`]): every vertex gets the same `color` (if the shader
+/// has a gradient), and texture coordinates that stretch a texture across the
+/// shape (if the shader is for graphics).
+///
+/// Flat shapes face the canvas when drawn without a camera, and start at 0
+/// (X right and Y down, like the canvas), filling the square from 0 to 1
+/// unless they're given a size.  Solid shapes are centered on the origin (the
+/// cube and sphere are 1 unit across), and face outwards in a world with Y up
+/// (like [`FirstPersonCamera`](super::FirstPersonCamera)).  Each triangle is
+/// counter-clockwise when seen from the front, so back faces can be culled
+/// with a [`Pipeline`](super::Pipeline).
+impl ShapeBuilder {
+    /// Generate a square.
+    pub fn quad(shader: &ShaderBuilder, color: [f32; 4]) -> Self {
+        let outline = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let mut triangles = Triangles::default();
+        for i in [0, 3, 1, 1, 3, 2] {
+            let [x, y] = outline[i];
+            triangles.push([x, y, 0.0], [x, y]);
+        }
+        triangles.finish(shader, color)
+    }
+
+    /// Generate a circle with `segments` straight edges (at least 3).
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Group, Headless, Shader, ShapeBuilder,
+    ///     Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::video::rgb::SRgba8;
+    /// use cala::window::Frame;
+    ///
+    /// std::thread::spawn(|| {
+    ///     let circle = ShapeBuilder::circle(&builder(), [1.0; 4], 16);
+    ///     let shader = Shader::new(builder());
+    ///     let circle = circle.finish(&shader);
+    ///     let mut group = Group::new();
+    ///     group.write(0, &circle, &Transform::new());
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///         frame.draw(&shader, &group);
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(32, 32);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// let white = SRgba8::new(255, 255, 255, 255);
+    /// assert_eq!(gpu.raster().pixel(16, 1), white);
+    /// assert_eq!(gpu.raster().pixel(8, 24), white);
+    /// assert_ne!(gpu.raster().pixel(1, 1), white);
+    /// ```
+    pub fn circle(
+        shader: &ShaderBuilder,
+        color: [f32; 4],
+        segments: u32,
+    ) -> Self {
+        assert!(segments >= 3);
+        let outline: Vec<_> = (0..segments)
+            .map(|i| {
+                let angle = i as f32 / segments as f32 * 2.0 * PI;
+                [0.5 + 0.5 * angle.cos(), 0.5 + 0.5 * angle.sin()]
+            })
+            .collect();
+        fan(&outline, [1.0, 1.0]).finish(shader, color)
+    }
+
+    /// Generate a `width` by `height` rectangle (from 0 to each), with
+    /// corners rounded to `radius` with `segments` straight edges each.
+    pub fn rounded_rect(
+        shader: &ShaderBuilder,
+        color: [f32; 4],
+        (width, height): (f32, f32),
+        radius: f32,
+        segments: u32,
+    ) -> Self {
+        assert!(segments >= 1);
+        let radius = radius.clamp(0.0, width.min(height) / 2.0);
+        // Corner centers, clockwise from the bottom right (Y is down).
+        let corners = [
+            [width - radius, height - radius],
+            [radius, height - radius],
+            [radius, radius],
+            [width - radius, radius],
+        ];
+        let mut outline = Vec::new();
+        for (i, corner) in corners.iter().enumerate() {
+            for j in 0..=segments {
+                let turn = (i as f32 + j as f32 / segments as f32) / 4.0;
+                let angle = turn * 2.0 * PI;
+                outline.push([
+                    corner[0] + radius * angle.cos(),
+                    corner[1] + radius * angle.sin(),
+                ]);
+            }
+        }
+        fan(&outline, [width, height]).finish(shader, color)
+    }
+
+    /// Generate a cube, with the whole texture on each side.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, FirstPersonCamera, Group, Headless, Shader,
+    ///     ShaderBuilder, ShapeBuilder, Texture, Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::video::{rgb::SRgba8, Raster};
+    /// use cala::window::Frame;
+    ///
+    /// let red = SRgba8::new(255, 0, 0, 255);
+    /// let green = SRgba8::new(0, 255, 0, 255);
+    /// std::thread::spawn(move || {
+    ///     let builder = ShaderBuilder {
+    ///         gradient: false,
+    ///         graphic: true,
+    ///         depth: true,
+    ///         ..builder()
+    ///     };
+    ///     let cube = ShapeBuilder::cube(&builder, [1.0; 4]);
+    ///     let shader = Shader::new(builder);
+    ///     let cube = cube.finish(&shader);
+    ///     let mut group = Group::new();
+    ///     group.write(0, &cube, &Transform::new());
+    ///     // Red on the left of each side, green on the right.
+    ///     let raster = Raster::with_pixels(2, 1, [red, green]);
+    ///     let texture = Texture::new(&raster);
+    ///     let camera = FirstPersonCamera::new([0.0, 0.0, 2.0]);
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///         frame.use_camera(&camera);
+    ///         frame.draw_graphic(&shader, &group, &texture);
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// // The front side (not the back side, seen from behind).
+    /// assert_eq!(gpu.raster().pixel(3, 3), red);
+    /// assert_eq!(gpu.raster().pixel(4, 3), green);
+    /// ```
+    pub fn cube(shader: &ShaderBuilder, color: [f32; 4]) -> Self {
+        // The outward normal of each side, and the directions of the
+        // texture's X and -Y across it.
+        #[rustfmt::skip]
+        let sides = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+        let mut triangles = Triangles::default();
+        for (normal, across, up) in sides.iter() {
+            let corner = |s: f32, t: f32| {
+                let mut position = [0.0; 3];
+                for (i, p) in position.iter_mut().enumerate() {
+                    *p = 0.5 * normal[i]
+                        + (s - 0.5) * across[i]
+                        + (t - 0.5) * up[i];
+                }
+                (position, [s, 1.0 - t])
+            };
+            let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+            for i in [0, 1, 2, 0, 2, 3] {
+                let (position, texcoord) = corner(corners[i].0, corners[i].1);
+                triangles.push(position, texcoord);
+            }
+        }
+        triangles.finish(shader, color)
+    }
+
+    /// Generate a sphere with `segments` slices around the Y axis (at least
+    /// 3), and `rings` bands from top to bottom (at least 2).  The texture
+    /// wraps around it once.
+    pub fn sphere(
+        shader: &ShaderBuilder,
+        color: [f32; 4],
+        segments: u32,
+        rings: u32,
+    ) -> Self {
+        assert!(segments >= 3 && rings >= 2);
+        let profile: Vec<_> = (0..=rings)
+            .map(|j| {
+                let v = j as f32 / rings as f32;
+                let angle = v * PI;
+                // Exactly at the axis at the poles.
+                let radius = if j == 0 || j == rings {
+                    0.0
+                } else {
+                    0.5 * angle.sin()
+                };
+                (radius, 0.5 * angle.cos(), v)
+            })
+            .collect();
+        lathe(&profile, segments).finish(shader, color)
+    }
+
+    /// Generate a capsule along the Y axis: a cylinder `length` long with a
+    /// half sphere on each end, all with `radius`.  It has `segments` slices
+    /// around the Y axis (at least 3), and `rings` bands in each half sphere
+    /// (at least 1).  The texture wraps around it once.
+    pub fn capsule(
+        shader: &ShaderBuilder,
+        color: [f32; 4],
+        radius: f32,
+        length: f32,
+        segments: u32,
+        rings: u32,
+    ) -> Self {
+        assert!(segments >= 3 && rings >= 1);
+        let height = length + 2.0 * radius;
+        let mut profile = Vec::new();
+        for (center, angles) in [
+            (length / 2.0, 0..=rings),
+            (-length / 2.0, rings..=2 * rings),
+        ] {
+            for j in angles {
+                let angle = j as f32 / rings as f32 * PI / 2.0;
+                let r = if j == 0 || j == 2 * rings {
+                    0.0
+                } else {
+                    radius * angle.sin()
+                };
+                let y = center + radius * angle.cos();
+                profile.push((r, y, (height / 2.0 - y) / height));
+            }
+        }
+        lathe(&profile, segments).finish(shader, color)
+    }
+}
@@ -0,0 +1,317 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{GpuCmd, Id, Internal, Shader, ShaderBuilder};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Result,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Which part of a shader an error is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    /// The vertex shader.
+    Vertex,
+    /// The fragment shader.
+    Fragment,
+    /// Linking the vertex and fragment shaders together.
+    Link,
+}
+
+/// An error from compiling shader source that was loaded at runtime.
+///
+/// Shaders with errors draw magenta, and shaders that fail to reload keep
+/// drawing with their previous source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderError {
+    /// The file that has the error (`None` if the source didn't come from a
+    /// file, or if it's a link error).
+    pub path: Option<PathBuf>,
+    /// Which part of the shader has the error.
+    pub stage: ShaderStage,
+    /// The error message from the GPU driver.
+    pub log: String,
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let stage = match self.stage {
+            ShaderStage::Vertex => "compiling vertex shader",
+            ShaderStage::Fragment => "compiling fragment shader",
+            ShaderStage::Link => "linking shader",
+        };
+        match self.path {
+            Some(ref path) => write!(f, "Error {} {}:", stage, path.display())?,
+            None => write!(f, "Error {}:", stage)?,
+        }
+        write!(f, " {}", self.log.trim_end())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Get the errors from shaders that failed to compile on the GPU since the
+/// last call.
+///
+/// Shaders are compiled when the next frame is rendered, so errors show up
+/// one frame after the shader is created or reloaded.
+pub fn shader_errors() -> Vec<ShaderError> {
+    let internal = Internal::new_lazy();
+    let mut errors = internal.shader_errors.lock().unwrap();
+    errors.drain(..).collect()
+}
+
+// Where a shader's source was loaded from, and the source it's built from.
+pub(super) struct ShaderFiles {
+    // Vertex and fragment shader files (`None` if the source didn't come
+    // from files).
+    paths: Option<(PathBuf, PathBuf)>,
+    // Settings of the shader: tint, gradient, graphic, depth and blend.
+    settings: [bool; 5],
+    // When the files were last modified, if they're being watched.
+    modified: Option<(SystemTime, SystemTime)>,
+    source: Source,
+}
+
+impl ShaderFiles {
+    // Keep `source` for a shader with the settings of `builder`.
+    fn new(builder: ShaderBuilder, source: Source) -> Self {
+        ShaderFiles {
+            paths: None,
+            settings: [
+                builder.tint,
+                builder.gradient,
+                builder.graphic,
+                builder.depth,
+                builder.blend,
+            ],
+            modified: None,
+            source,
+        }
+    }
+
+    // Read the shader source from the files.
+    fn read(vert: &Path, frag: &Path) -> Result<Source> {
+        let vert = std::fs::read_to_string(vert)?;
+        let frag = std::fs::read_to_string(frag)?;
+        Ok(Source::new(&vert, &frag))
+    }
+
+    // Get the settings to build the shader with `source`.
+    fn builder(&self, source: &Source) -> ShaderBuilder {
+        let [tint, gradient, graphic, depth, blend] = self.settings;
+        // Safety: commands are the only users of the builder, and they've all
+        // run by the time a retired source is freed (see `retired()`).
+        let (frag, vert): (&'static str, &'static str) = unsafe {
+            (
+                &*(&*source.frag as *const str),
+                &*(&*source.vert as *const str),
+            )
+        };
+        ShaderBuilder {
+            tint,
+            gradient,
+            graphic,
+            depth,
+            blend,
+            opengl_frag: frag,
+            opengl_vert: vert,
+        }
+    }
+}
+
+// Shader source, nul-terminated.  `ShaderBuilder` needs it to be `'static`,
+// so it's kept until the draw thread is done with it, instead of leaked.
+pub(super) struct Source {
+    vert: Box<str>,
+    frag: Box<str>,
+}
+
+impl Source {
+    fn new(vert: &str, frag: &str) -> Self {
+        Source {
+            vert: format!("{}\0", vert).into_boxed_str(),
+            frag: format!("{}\0", frag).into_boxed_str(),
+        }
+    }
+}
+
+// Free a source after the commands that use it have run.
+fn retire(source: Source) {
+    let internal = Internal::new_lazy();
+    internal.retired_sources.lock().unwrap().push(source);
+}
+
+// Take the sources retired before the command buffer was drained, to free
+// once the drained commands have run.  The command buffer must be locked, so
+// that every command using them has been drained.
+pub(super) fn retired() -> Vec<Source> {
+    let internal = Internal::new_lazy();
+    std::mem::take(&mut *internal.retired_sources.lock().unwrap())
+}
+
+// Get when both files were last modified.
+fn modified(vert: &Path, frag: &Path) -> Result<(SystemTime, SystemTime)> {
+    Ok((
+        std::fs::metadata(vert)?.modified()?,
+        std::fs::metadata(frag)?.modified()?,
+    ))
+}
+
+impl Shader {
+    /// Create a shader from GLSL source at runtime, with the settings
+    /// (tint, gradient, graphic, depth and blend) of `builder`.
+    ///
+    /// The source is compiled on the GPU when the next frame is rendered, and
+    /// errors are reported by [`shader_errors()`] instead of panicking.
+    pub fn from_source(builder: ShaderBuilder, vert: &str, frag: &str) -> Self {
+        Self::register(ShaderFiles::new(builder, Source::new(vert, frag)))
+    }
+
+    /// Create a shader from GLSL source files, with the settings of
+    /// `builder` (like [`from_source()`](Shader::from_source)).
+    pub fn load<P: AsRef<Path>>(
+        builder: ShaderBuilder,
+        vert: P,
+        frag: P,
+    ) -> Result<Self> {
+        Self::open(builder, vert.as_ref(), frag.as_ref(), false)
+    }
+
+    /// Create a shader from GLSL source files (like
+    /// [`load()`](Shader::load)), that is rebuilt whenever the files change.
+    ///
+    /// Files are only watched in debug builds, for trying out changes without
+    /// rebuilding.  If the new source has errors, they're reported by
+    /// [`shader_errors()`] and the shader keeps its previous source.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, resource_stats, shader_errors, Headless, Shader,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::window::Frame;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let dir = std::env::temp_dir().join("cala-shader-watch");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let (vert, frag) = (dir.join("color.vert"), dir.join("color.frag"));
+    /// std::fs::write(&vert, "void main() {}").unwrap();
+    /// std::fs::write(&frag, "void main() {}").unwrap();
+    /// let _shader = Shader::watch(builder(), &vert, &frag).unwrap();
+    ///
+    /// std::thread::spawn(|| {
+    ///     exec!({
+    ///         for _ in 0..2 {
+    ///             let _frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///             // Check every frame.
+    ///             for error in shader_errors() {
+    ///                 eprintln!("{}", error);
+    ///             }
+    ///         }
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// gpu.run(Duration::from_millis(16));
+    /// assert_eq!(resource_stats().shaders.bytes, 30);
+    /// // Edit the fragment shader.
+    /// std::fs::write(&frag, "void main() { discard; }").unwrap();
+    /// let file = std::fs::File::options().write(true).open(&frag).unwrap();
+    /// file.set_modified(SystemTime::now() + Duration::from_secs(1))
+    ///     .unwrap();
+    /// gpu.run(Duration::from_millis(16));
+    /// assert_eq!(resource_stats().shaders.bytes, 40);
+    /// ```
+    pub fn watch<P: AsRef<Path>>(
+        builder: ShaderBuilder,
+        vert: P,
+        frag: P,
+    ) -> Result<Self> {
+        let watch = cfg!(debug_assertions);
+        Self::open(builder, vert.as_ref(), frag.as_ref(), watch)
+    }
+
+    fn open(
+        builder: ShaderBuilder,
+        vert: &Path,
+        frag: &Path,
+        watch: bool,
+    ) -> Result<Self> {
+        let mut files =
+            ShaderFiles::new(builder, ShaderFiles::read(vert, frag)?);
+        files.paths = Some((vert.to_path_buf(), frag.to_path_buf()));
+        if watch {
+            files.modified = Some(modified(vert, frag)?);
+        }
+        Ok(Self::register(files))
+    }
+
+    // Create a shader from source that was loaded at runtime, and keep the
+    // source for as long as the shader uses it.
+    fn register(files: ShaderFiles) -> Self {
+        let shader = Shader::new(files.builder(&files.source));
+        let internal = Internal::new_lazy();
+        let mut list = internal.shader_files.lock().unwrap();
+        list.insert(shader.0, files);
+        shader
+    }
+}
+
+// Forget where a shader's source came from, when it's dropped (with the
+// command buffer locked, so it isn't reloaded after it's deleted).
+pub(super) fn forget(id: Id) {
+    let internal = Internal::new_lazy();
+    let files = internal.shader_files.lock().unwrap().remove(&id);
+    if let Some(files) = files {
+        retire(files.source);
+    }
+}
+
+// Rebuild shaders with files that changed since the last frame.
+pub(super) fn poll() {
+    let internal = Internal::new_lazy();
+    let mut cmds = internal.cmds.lock().unwrap();
+    let mut list = internal.shader_files.lock().unwrap();
+    for (id, files) in list.iter_mut() {
+        let (vert, frag) = match files.paths {
+            Some((ref vert, ref frag)) => (vert, frag),
+            None => continue,
+        };
+        let now = match (files.modified, modified(vert, frag)) {
+            (Some(then), Ok(now)) if then != now => now,
+            _ => continue,
+        };
+        // If the files can't be read (like while they're being saved), try
+        // again next frame.
+        if let Ok(source) = ShaderFiles::read(vert, frag) {
+            files.modified = Some(now);
+            cmds.push(GpuCmd::ShaderId(files.builder(&source), *id));
+            retire(std::mem::replace(&mut files.source, source));
+        }
+    }
+}
+
+// Report that a shader failed to compile.
+pub(super) fn report(id: Id, stage: ShaderStage, log: String) {
+    let internal = Internal::new_lazy();
+    let list = internal.shader_files.lock().unwrap();
+    let paths = list.get(&id).and_then(|files| files.paths.as_ref());
+    let path = paths.and_then(|(vert, frag)| match stage {
+        ShaderStage::Vertex => Some(vert.clone()),
+        ShaderStage::Fragment => Some(frag.clone()),
+        ShaderStage::Link => None,
+    });
+    let error = ShaderError { path, stage, log };
+    internal.shader_errors.lock().unwrap().push(error);
+}
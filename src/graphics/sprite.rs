@@ -0,0 +1,250 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    atlas::Atlas, Group, Region, Shader, ShaderBuilder, Shape, ShapeBuilder,
+    Texture, Transform,
+};
+use pix::{el::Pixel, rgb::SRgba8, Raster};
+use std::collections::HashMap;
+
+/// How a sprite is mirrored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Flip {
+    /// Not mirrored.
+    None,
+    /// Mirrored left to right.
+    Horizontal,
+    /// Mirrored top to bottom.
+    Vertical,
+    /// Mirrored both ways (same as rotating 180°).
+    Both,
+}
+
+/// A handle to a sprite in a [`SpriteAtlas`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sprite(Region);
+
+impl Sprite {
+    /// Get the width of the sprite in pixels.
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// Get the height of the sprite in pixels.
+    pub fn height(&self) -> u32 {
+        self.0.height()
+    }
+}
+
+/// Many sprites packed into one [`Texture`].
+pub struct SpriteAtlas {
+    shader: Shader,
+    atlas: Atlas,
+}
+
+impl Default for SpriteAtlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteAtlas {
+    /// Create an empty `SpriteAtlas`.  It grows as sprites are added.
+    pub fn new() -> Self {
+        SpriteAtlas {
+            shader: Shader::new(include!(concat!(
+                env!("OUT_DIR"),
+                "/res/sprite.rs"
+            ))),
+            atlas: Atlas::new(256),
+        }
+    }
+
+    /// Add a sprite to the atlas.
+    pub fn add<P: Pixel>(&mut self, raster: &Raster<P>) -> Sprite
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        Sprite(self.atlas.add(&Raster::with_raster(raster)))
+    }
+
+    /// Get the texture coordinates (offset and size) of a sprite, for
+    /// [`Group::write_tex()`].  These change when the atlas grows.
+    pub fn uv(&self, sprite: Sprite) -> ([f32; 2], [f32; 2]) {
+        self.atlas.coords(sprite.0)
+    }
+
+    /// Get the atlas texture.
+    pub fn texture(&self) -> &Texture {
+        self.atlas.texture()
+    }
+}
+
+/// Sprites to draw in one batch, with
+/// [`Canvas::draw_sprites()`](super::Canvas::draw_sprites).
+///
+/// Sprites are drawn in the order they're pushed, and the batch is emptied
+/// after it's drawn.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Flip, Headless, SpriteAtlas, SpriteBatch,
+///     Transform,
+/// };
+/// use cala::task::exec;
+/// use cala::video::{rgb::SRgba8, Raster};
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let mut atlas = SpriteAtlas::new();
+///     let mut raster = Raster::<SRgba8>::with_clear(2, 1);
+///     *raster.pixel_mut(0, 0) = SRgba8::new(255, 0, 0, 255);
+///     *raster.pixel_mut(1, 0) = SRgba8::new(0, 0, 255, 255);
+///     let sprite = atlas.add(&raster);
+///     let mut batch = SpriteBatch::new();
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         let white = SRgba8::new(255, 255, 255, 255);
+///         batch.push(sprite, &Transform::new(), Flip::Horizontal, white);
+///         frame.draw_sprites(&atlas, &mut batch);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(0, 4), SRgba8::new(0, 0, 255, 255));
+/// assert_eq!(gpu.raster().pixel(7, 4), SRgba8::new(255, 0, 0, 255));
+/// ```
+pub struct SpriteBatch {
+    group: Group,
+    sprites: Vec<(Sprite, Transform, Flip, SRgba8)>,
+    // Number of sprites in the group that aren't hidden.
+    written: usize,
+    // A quad for each tint used by the last batch (tints are vertex colors).
+    quads: HashMap<[u8; 4], Shape>,
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteBatch {
+    /// Create an empty `SpriteBatch`.
+    pub fn new() -> Self {
+        SpriteBatch {
+            group: Group::new(),
+            sprites: Vec::new(),
+            written: 0,
+            quads: HashMap::new(),
+        }
+    }
+
+    /// Add a sprite to the batch.  The `transform` is applied to a 1x1
+    /// square (in [`Canvas`](super::Canvas) coordinates), and the sprite's
+    /// colors are multiplied by the `tint`.
+    pub fn push<P: Pixel>(
+        &mut self,
+        sprite: Sprite,
+        transform: &Transform,
+        flip: Flip,
+        tint: P,
+    ) where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        self.sprites
+            .push((sprite, *transform, flip, tint.convert()));
+    }
+
+    // Write the sprites into the group, and empty the batch.
+    pub(super) fn prepare<'a>(
+        &mut self,
+        atlas: &'a SpriteAtlas,
+    ) -> (&'a Shader, &'a Texture) {
+        let count = self.sprites.len();
+        // Quads for tints that aren't used any more are dropped at the end.
+        let mut unused = std::mem::take(&mut self.quads);
+        for (id, (sprite, transform, flip, tint)) in
+            self.sprites.drain(..).enumerate()
+        {
+            let quad = reuse(&mut self.quads, &mut unused, &atlas.shader, tint);
+            // Flip with texture coordinates, because flipping with the
+            // transform would turn the quad around so it's culled.
+            let ([x, y], [w, h]) = atlas.uv(sprite);
+            let coords = match flip {
+                Flip::None => ([x, y], [w, h]),
+                Flip::Horizontal => ([x + w, y], [-w, h]),
+                Flip::Vertical => ([x, y + h], [w, -h]),
+                Flip::Both => ([x + w, y + h], [-w, -h]),
+            };
+            self.group.write_tex(id as u32, quad, &transform, coords);
+        }
+        // Hide sprites left over from a bigger batch by shrinking them away.
+        if count < self.written {
+            let white = SRgba8::new(255, 255, 255, 255);
+            let quad =
+                reuse(&mut self.quads, &mut unused, &atlas.shader, white);
+            let hidden = Transform::new().scale(0.0, 0.0, 0.0);
+            for id in count..self.written {
+                self.group.write(id as u32, quad, &hidden);
+            }
+        }
+        self.written = count;
+        (&atlas.shader, atlas.texture())
+    }
+
+    pub(super) fn group(&self) -> &Group {
+        &self.group
+    }
+}
+
+// Get a hashable tint.
+pub(super) fn key(tint: SRgba8) -> [u8; 4] {
+    [
+        u8::from(tint.one()),
+        u8::from(tint.two()),
+        u8::from(tint.three()),
+        u8::from(tint.four()),
+    ]
+}
+
+// Get the quad for a tint, moving it from `unused` if it was made before.
+fn reuse<'a>(
+    quads: &'a mut HashMap<[u8; 4], Shape>,
+    unused: &mut HashMap<[u8; 4], Shape>,
+    shader: &Shader,
+    tint: SRgba8,
+) -> &'a Shape {
+    quads.entry(key(tint)).or_insert_with(|| {
+        unused
+            .remove(&key(tint))
+            .unwrap_or_else(|| quad(shader, tint))
+    })
+}
+
+// Create a 1x1 quad with a vertex color.
+pub(super) fn quad(shader: &Shader, tint: SRgba8) -> Shape {
+    let [r, g, b, a] = key(tint).map(|channel| f32::from(channel) / 255.0);
+    #[rustfmt::skip]
+    let vertices = [
+        0.0, 0.0, 0.0, 0.0, r, g, b, a,
+        0.0, 1.0, 0.0, 1.0, r, g, b, a,
+        1.0, 0.0, 1.0, 0.0, r, g, b, a,
+        1.0, 0.0, 1.0, 0.0, r, g, b, a,
+        0.0, 1.0, 0.0, 1.0, r, g, b, a,
+        1.0, 1.0, 1.0, 1.0, r, g, b, a,
+    ];
+    ShapeBuilder::new()
+        .vert(&vertices)
+        .face(Transform::new())
+        .finish(shader)
+}
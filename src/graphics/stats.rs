@@ -0,0 +1,301 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{stride, GpuCmd, Id, Internal};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::Duration,
+};
+
+/// Memory used by one kind of GPU resource.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// How many are alive on the GPU.
+    pub count: usize,
+    /// How many bytes of data they take up.
+    pub bytes: usize,
+}
+
+/// Statistics for the resources that are alive on the GPU.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    /// [`Texture`](super::Texture)s (4 bytes per pixel).
+    pub textures: Usage,
+    /// [`Shader`](super::Shader)s (bytes of shader source).
+    pub shaders: Usage,
+    /// [`Shape`](super::Shape)s (bytes of vertex data).
+    pub shapes: Usage,
+    /// [`Group`](super::Group)s (bytes of vertex data from the shapes written
+    /// to them).
+    pub groups: Usage,
+}
+
+/// Get statistics for the resources that are alive on the GPU, as of the
+/// most recent frame.
+///
+/// Resources are created and deleted on the GPU when the next frame is
+/// rendered, so creating or dropping resources doesn't change the statistics
+/// until then.
+///
+/// ```rust
+/// use cala::graphics::{color::SRgb32, resource_stats, Headless, Texture};
+/// use cala::task::exec;
+/// use cala::video::{rgb::SRgba8, Raster};
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let raster = Raster::<SRgba8>::with_clear(4, 4);
+///     let mut texture = Some(Texture::new(&raster));
+///     let mut frames = 0;
+///     exec!({
+///         let _frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         // Drop the texture during the second frame.
+///         frames += 1;
+///         if frames == 2 {
+///             texture = None;
+///         }
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(resource_stats().textures.count, 1);
+/// assert_eq!(resource_stats().textures.bytes, 4 * 4 * 4);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(resource_stats().textures.count, 0);
+/// ```
+pub fn resource_stats() -> ResourceStats {
+    Internal::new_lazy().accounting.lock().unwrap().stats()
+}
+
+// Bytes used by (or vertices of) each live resource of one kind, indexed by
+// slot.
+#[derive(Default)]
+struct Sizes(Vec<Option<usize>>);
+
+impl Sizes {
+    fn set(&mut self, id: Id, bytes: usize) {
+        let id = id.index as usize;
+        if id >= self.0.len() {
+            self.0.resize(id + 1, None);
+        }
+        self.0[id] = Some(bytes);
+    }
+
+    fn get(&self, id: Id) -> usize {
+        self.0
+            .get(id.index as usize)
+            .cloned()
+            .flatten()
+            .unwrap_or(0)
+    }
+
+    fn free(&mut self, id: Id) {
+        if let Some(size) = self.0.get_mut(id.index as usize) {
+            *size = None;
+        }
+    }
+
+    fn usage(&self) -> Usage {
+        self.0
+            .iter()
+            .flatten()
+            .fold(Usage::default(), |usage, bytes| Usage {
+                count: usage.count + 1,
+                bytes: usage.bytes + bytes,
+            })
+    }
+}
+
+// Keeps track of resources as commands are sent to the GPU.
+#[derive(Default)]
+pub(super) struct Accounting {
+    textures: Sizes,
+    shaders: Sizes,
+    shapes: Sizes,
+    // Floats in each vertex of each shader's shapes.
+    strides: Sizes,
+    vertices: Sizes,
+    // Bytes and vertices of each shape written to each group.
+    groups: Vec<Option<Vec<(usize, usize)>>>,
+}
+
+impl Accounting {
+    // Update statistics for a command that's about to be executed, adding it
+    // to the statistics for the `frame`.
+    pub(super) fn account(&mut self, cmd: &GpuCmd, frame: &mut FrameStats) {
+        use GpuCmd::*;
+        frame.commands += 1;
+        match cmd {
+            Draw(_shader, group, _) | DrawGraphic(_shader, group, _, _) => {
+                frame.draw_calls += 1;
+                if let Some(Some(slots)) = self.groups.get(group.index as usize)
+                {
+                    frame.vertices += slots.iter().map(|s| s.1).sum::<usize>();
+                }
+            }
+            RasterId(raster, id) => {
+                frame.texture_uploads += 1;
+                self.textures.set(*id, raster.as_u8_slice().len())
+            }
+            RasterUpdate(_id, _region, _raster) => frame.texture_uploads += 1,
+            ShaderId(builder, id) => {
+                self.shaders.set(
+                    *id,
+                    builder.opengl_frag.len() + builder.opengl_vert.len(),
+                );
+                self.strides.set(*id, stride(builder));
+            }
+            ShapeId(builder, id, shader) => {
+                let mut floats = 0;
+                let mut vertices = 0;
+                for face in builder.faces.iter() {
+                    if let Some(ref v) = face.vertices {
+                        vertices = v.len();
+                    }
+                    if face.transform.is_some() {
+                        floats += vertices;
+                    }
+                }
+                self.shapes.set(*id, floats * std::mem::size_of::<f32>());
+                let stride = self.strides.get(*shader).max(1);
+                self.vertices.set(*id, floats / stride);
+            }
+            GroupId(id) => {
+                let id = id.index as usize;
+                if id >= self.groups.len() {
+                    self.groups.resize(id + 1, None);
+                }
+                self.groups[id] = Some(Vec::new());
+            }
+            GroupWrite(group, id, shape, _)
+            | GroupWriteTex(group, id, shape, _, _) => {
+                let size = (self.shapes.get(*shape), self.vertices.get(*shape));
+                if let Some(Some(slots)) =
+                    self.groups.get_mut(group.index as usize)
+                {
+                    let id = *id as usize;
+                    if id >= slots.len() {
+                        slots.resize(id + 1, (0, 0));
+                    }
+                    slots[id] = size;
+                }
+            }
+            InstanceWrite(group, shape, instances) => {
+                let size = (self.shapes.get(*shape), self.vertices.get(*shape));
+                if let Some(Some(slots)) =
+                    self.groups.get_mut(group.index as usize)
+                {
+                    for (id, _transform, _tint) in instances.iter() {
+                        let id = *id as usize;
+                        if id >= slots.len() {
+                            slots.resize(id + 1, (0, 0));
+                        }
+                        slots[id] = size;
+                    }
+                }
+            }
+            RasterDrop(id) => self.textures.free(*id),
+            ShaderDrop(id) => {
+                self.shaders.free(*id);
+                self.strides.free(*id);
+            }
+            ShapeDrop(id) => {
+                self.shapes.free(*id);
+                self.vertices.free(*id);
+            }
+            GroupDrop(id) => {
+                if let Some(slots) = self.groups.get_mut(id.index as usize) {
+                    *slots = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn stats(&self) -> ResourceStats {
+        let groups = self.groups.iter().flatten().fold(
+            Usage::default(),
+            |usage, slots| Usage {
+                count: usage.count + 1,
+                bytes: usage.bytes + slots.iter().map(|s| s.0).sum::<usize>(),
+            },
+        );
+        ResourceStats {
+            textures: self.textures.usage(),
+            shaders: self.shaders.usage(),
+            shapes: self.shapes.usage(),
+            groups,
+        }
+    }
+}
+
+/// Statistics for one frame: the commands it sent to the GPU, and how long
+/// the draw thread took to render it.
+///
+/// Get them from [`Frame::stats()`](crate::window::Frame::stats), or log the
+/// slow ones with `log_frame_stats()` (with the **log** feature).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of commands sent to the GPU (creating, changing, dropping and
+    /// drawing resources).
+    pub commands: usize,
+    /// Number of groups drawn.
+    pub draw_calls: usize,
+    /// Number of vertices in the groups drawn.
+    pub vertices: usize,
+    /// Number of textures created or updated.
+    pub texture_uploads: usize,
+    /// Time the draw thread spent waiting for the frame to be dropped.
+    pub waiting: Duration,
+    /// Time the draw thread spent running commands (OpenGL may still be
+    /// drawing after this).
+    pub processing: Duration,
+}
+
+impl Display for FrameStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} commands, {} draw calls, {} vertices, {} texture uploads; \
+             waited {:?}, processed in {:?}",
+            self.commands,
+            self.draw_calls,
+            self.vertices,
+            self.texture_uploads,
+            self.waiting,
+            self.processing,
+        )
+    }
+}
+
+#[cfg(feature = "log")]
+const FRAME: crate::log::Tag = crate::log::Tag::new("Frame").show(true);
+
+/// Log the statistics of every frame that takes longer than `threshold` to
+/// render (waiting and processing), or stop logging them with `None`.  Use
+/// `Some(Duration::ZERO)` to log every frame.
+#[cfg(feature = "log")]
+pub fn log_frame_stats(threshold: Option<Duration>) {
+    *Internal::new_lazy().frame_log.lock().unwrap() = threshold;
+}
+
+// Save the statistics of the frame that was just rendered, for the next
+// `Frame`.
+pub(super) fn rendered(stats: FrameStats) {
+    let internal = Internal::new_lazy();
+    #[cfg(feature = "log")]
+    if let Some(threshold) = *internal.frame_log.lock().unwrap() {
+        if stats.waiting + stats.processing >= threshold {
+            crate::log::log!(FRAME, "{}", stats);
+        }
+    }
+    *internal.frame_stats.lock().unwrap() = stats;
+}
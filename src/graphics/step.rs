@@ -0,0 +1,140 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::Canvas;
+use std::time::Duration;
+
+/// Runs a simulation in fixed-length ticks, no matter how long frames take.
+///
+/// Each frame, [`advance()`](FixedStep::advance) adds the frame's elapsed
+/// time and returns how many ticks to simulate, then
+/// [`alpha()`](FixedStep::alpha) says how far the leftover time is into the
+/// next tick, for interpolating between the last two simulated states when
+/// drawing.  If frames fall too far behind, the extra time is skipped rather
+/// than simulated (so slow ticks can't make every frame slower).
+///
+/// ```rust
+/// use cala::graphics::{color::SRgb32, Canvas, FixedStep, Headless};
+/// use cala::task::exec;
+/// use cala::window::Frame;
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// let ticks = Arc::new(Mutex::new(Vec::new()));
+/// let list = ticks.clone();
+/// std::thread::spawn(move || {
+///     // 50 ticks a second.
+///     let mut step = FixedStep::new(50);
+///     let mut position = 0.0;
+///     exec!({
+///         let frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         let mut previous = position;
+///         let count = step.advance(&frame);
+///         for _ in 0..count {
+///             previous = position;
+///             position += step.step().as_secs_f32();
+///         }
+///         // Draw at the interpolated position.
+///         let _drawn = previous + (position - previous) * step.alpha();
+///         list.lock().unwrap().push(count);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// for ms in [30, 30, 500] {
+///     gpu.run(Duration::from_millis(ms));
+/// }
+/// // 1.5, then 1.5 more (3), then too many ticks.
+/// assert_eq!(*ticks.lock().unwrap(), [1, 2, 8]);
+///
+/// let mut step = FixedStep::new(50).max_ticks(2);
+/// assert_eq!(step.advance_by(Duration::from_millis(30)), 1);
+/// assert_eq!(step.alpha(), 0.5);
+/// assert_eq!(step.advance_by(Duration::from_millis(500)), 2);
+/// assert_eq!(step.alpha(), 0.5);
+/// assert_eq!(step.ticks(), 3);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FixedStep {
+    step: Duration,
+    max_ticks: u32,
+    // Time that hasn't been simulated yet.
+    accumulated: Duration,
+    ticks: u64,
+}
+
+impl FixedStep {
+    /// Create a `FixedStep` that runs `rate` ticks per second, and at most 8
+    /// ticks per frame.
+    pub fn new(rate: u32) -> Self {
+        assert!(rate > 0);
+        Self::with_step(Duration::from_secs(1) / rate)
+    }
+
+    /// Create a `FixedStep` with ticks that are each `step` long, and at most
+    /// 8 ticks per frame.
+    pub fn with_step(step: Duration) -> Self {
+        assert!(step > Duration::from_secs(0));
+        FixedStep {
+            step,
+            max_ticks: 8,
+            accumulated: Duration::from_secs(0),
+            ticks: 0,
+        }
+    }
+
+    /// Set the most ticks to run in one frame (default: 8).  Time beyond that
+    /// is skipped, slowing down the simulation until frames catch up.
+    pub fn max_ticks(mut self, max_ticks: u32) -> Self {
+        assert!(max_ticks > 0);
+        self.max_ticks = max_ticks;
+        self
+    }
+
+    /// Get the length of each tick.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Get the total number of ticks so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Add the time elapsed since the previous frame of a `canvas`, and get
+    /// the number of ticks to simulate.
+    pub fn advance<C: Canvas>(&mut self, canvas: &C) -> u32 {
+        self.advance_by(canvas.elapsed())
+    }
+
+    /// Add `elapsed` time, and get the number of ticks to simulate.
+    pub fn advance_by(&mut self, elapsed: Duration) -> u32 {
+        self.accumulated += elapsed;
+        let mut ticks = 0;
+        while self.accumulated >= self.step {
+            if ticks == self.max_ticks {
+                // Skip whole ticks, keeping the partial one for `alpha()`.
+                let behind = self.accumulated.as_nanos() % self.step.as_nanos();
+                self.accumulated = Duration::from_nanos(behind as u64);
+                break;
+            }
+            self.accumulated -= self.step;
+            ticks += 1;
+        }
+        self.ticks += u64::from(ticks);
+        ticks
+    }
+
+    /// Get how far into the next tick the leftover time is (from 0 to 1), for
+    /// interpolating between the last two ticks.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulated.as_secs_f64() / self.step.as_secs_f64()) as f32
+    }
+}
@@ -0,0 +1,229 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    Canvas, GpuCmd, Group, Internal, Pipeline, Shader, Texture, Transform,
+};
+use pix::{chan::Channel, el::Pixel, rgb::SRgba8, Raster};
+use std::marker::PhantomData;
+
+/// A [`Texture`] that can be drawn on with
+/// [`Frame::render_to()`](crate::window::Frame::render_to).
+pub struct RenderTarget {
+    texture: Texture,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTarget {
+    /// Create a new `width`×`height` pixel `RenderTarget`.
+    pub fn new(width: u32, height: u32) -> Self {
+        assert!(width > 0 && height > 0);
+        let raster = Raster::<SRgba8>::with_clear(width, height);
+        RenderTarget {
+            texture: Texture::new(&raster),
+            width,
+            height,
+        }
+    }
+
+    /// Get the texture, with whatever was last drawn on it.  Drawing with it
+    /// on itself (while it's the render target) samples transparent pixels.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Group, Headless, RenderTarget, Shader,
+    ///     ShaderBuilder, ShapeBuilder, Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::window::Frame;
+    ///
+    /// std::thread::spawn(|| {
+    ///     let builder = ShaderBuilder {
+    ///         gradient: false,
+    ///         graphic: true,
+    ///         ..builder()
+    ///     };
+    ///     let square = ShapeBuilder::quad(&builder, [1.0; 4]);
+    ///     let shader = Shader::new(builder);
+    ///     let square = square.finish(&shader);
+    ///     let mut group = Group::new();
+    ///     group.write(0, &square, &Transform::new());
+    ///     let target = RenderTarget::new(4, 4);
+    ///     exec!({
+    ///         let black = SRgb32::new(0.0, 0.0, 0.0);
+    ///         let mut frame = Frame::new(black).await;
+    ///         let mut offscreen = frame.render_to(&target, black);
+    ///         offscreen.draw_graphic(&shader, &group, target.texture());
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// ```
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+/// A [`Canvas`] that draws on a [`RenderTarget`] instead of the screen.
+///
+/// Returned from [`Frame::render_to()`](crate::window::Frame::render_to).
+/// Drawing goes back to the screen when it's dropped.  It borrows both the
+/// frame and the render target, so the target can't be dropped while it's
+/// being drawn on.
+///
+/// ```rust,compile_fail
+/// use cala::graphics::{color::SRgb32, RenderTarget};
+/// use cala::window::Frame;
+///
+/// async fn draw() {
+///     let black = SRgb32::new(0.0, 0.0, 0.0);
+///     let mut frame = Frame::new(black).await;
+///     let target = RenderTarget::new(2, 2);
+///     let offscreen = frame.render_to(&target, black);
+///     drop(target); // Error: `target` is still borrowed.
+///     drop(offscreen);
+/// }
+/// ```
+pub struct Offscreen<'a> {
+    width: u32,
+    height: u32,
+    elapsed: std::time::Duration,
+    _frame: PhantomData<&'a mut ()>,
+    _target: PhantomData<&'a RenderTarget>,
+}
+
+impl<'a> Offscreen<'a> {
+    // Start drawing on a render target, cleared to `color`.
+    pub(crate) fn new<P: Pixel>(
+        target: &'a RenderTarget,
+        color: P,
+        elapsed: std::time::Duration,
+    ) -> Self
+    where
+        pix::chan::Ch32: From<<P as Pixel>::Chan>,
+    {
+        let color: pix::rgb::SRgba32 = color.convert();
+        let color = [
+            color.one().to_f32(),
+            color.two().to_f32(),
+            color.three().to_f32(),
+            color.four().to_f32(),
+        ];
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::SetTarget(Some((
+            target.texture.0,
+            target.width,
+            target.height,
+            color,
+        ))));
+        Offscreen {
+            width: target.width,
+            height: target.height,
+            elapsed,
+            _frame: PhantomData,
+            _target: PhantomData,
+        }
+    }
+}
+
+impl Canvas for Offscreen<'_> {
+    fn draw(&mut self, shader: &Shader, group: &Group) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::Draw(shader.0, group.0, None));
+    }
+
+    fn draw_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        pipeline: Pipeline,
+    ) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::Draw(shader.0, group.0, Some(pipeline)));
+    }
+
+    fn set_camera(&mut self, camera: Transform) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::SetCamera(camera));
+    }
+
+    fn set_tint<P: Pixel>(&mut self, shader: &Shader, tint: P)
+    where
+        pix::chan::Ch32: From<<P as Pixel>::Chan>,
+    {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        let color: pix::rgb::SRgba32 = tint.convert();
+        let red = color.one().to_f32();
+        let green = color.two().to_f32();
+        let blue = color.three().to_f32();
+        let alpha = color.four().to_f32();
+        cmds.push(GpuCmd::SetTint(shader.0, [red, green, blue, alpha]));
+    }
+
+    fn draw_graphic(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        graphic: &Texture,
+    ) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::DrawGraphic(shader.0, group.0, graphic.0, None));
+    }
+
+    fn draw_graphic_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        graphic: &Texture,
+        pipeline: Pipeline,
+    ) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::DrawGraphic(
+            shader.0,
+            group.0,
+            graphic.0,
+            Some(pipeline),
+        ));
+    }
+
+    fn elapsed(&self) -> std::time::Duration {
+        self.elapsed
+    }
+
+    fn height(&self) -> f32 {
+        self.height as f32 / self.width as f32
+    }
+
+    fn resized(&self) -> bool {
+        false
+    }
+
+    fn pixel_width(&self) -> u32 {
+        self.width
+    }
+}
+
+impl Drop for Offscreen<'_> {
+    fn drop(&mut self) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::SetTarget(None));
+    }
+}
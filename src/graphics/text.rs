@@ -0,0 +1,434 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    atlas::Atlas, headless::mask, registry::Id, Group, Region, Shader,
+    ShaderBuilder, Shape, ShapeBuilder, Texture, Transform,
+};
+use footile::{FillRule, PathOp, Pt};
+use pix::{
+    el::Pixel,
+    matte::Matte8,
+    rgb::{SRgba32, SRgba8},
+    Raster,
+};
+use std::collections::HashMap;
+
+// Width of a line (in font heights) that's never wrapped.
+const NO_WRAP: f32 = 10_000.0;
+// Empty pixels around each glyph, so antialiased edges aren't cut off.
+const PADDING: u32 = 1;
+
+/// How lines of text are aligned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Lines start at the left edge.
+    Left,
+    /// Lines are centered.
+    Center,
+    /// Lines end at the right edge.
+    Right,
+}
+
+// A glyph's location in the atlas.
+#[derive(Copy, Clone)]
+struct Glyph {
+    // Number of path operations for the glyph.
+    ops: usize,
+    // First point of the glyph's path, relative to its origin.
+    first: Pt,
+    // Region of the atlas (pixels), empty if there's nothing to draw.
+    region: Region,
+    // Top-left corner of the atlas region, relative to the origin (in font
+    // heights).
+    offset: (f32, f32),
+}
+
+/// A font, with a glyph atlas cached in a [`Texture`].
+///
+/// Glyphs are rasterized into the atlas the first time they're used by a
+/// [`TextBuilder`].
+pub struct Font {
+    font: fonterator::Font<'static>,
+    // Height of glyphs in the atlas (pixels).
+    size: u32,
+    shader: Shader,
+    quad: Shape,
+    atlas: Atlas,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl Font {
+    /// Load a TTF or OTF font, with glyphs rasterized at `size` pixels tall.
+    pub fn new(data: &'static [u8], size: u32) -> Option<Self> {
+        Some(Self::with_font(fonterator::Font::new().push(data)?, size))
+    }
+
+    /// Get the built-in font, with glyphs rasterized at `size` pixels tall.
+    pub fn normal(size: u32) -> Self {
+        Self::with_font(fonterator::normal_font(), size)
+    }
+
+    fn with_font(font: fonterator::Font<'static>, size: u32) -> Self {
+        assert!(size > 0);
+        let shader =
+            Shader::new(include!(concat!(env!("OUT_DIR"), "/res/text.rs")));
+        #[rustfmt::skip]
+        let quad = ShapeBuilder::new()
+            .vert(&[
+                0.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 1.0,
+                1.0, 0.0, 1.0, 0.0,
+                1.0, 0.0, 1.0, 0.0,
+                0.0, 1.0, 0.0, 1.0,
+                1.0, 1.0, 1.0, 1.0,
+            ])
+            .face(Transform::new())
+            .finish(&shader);
+        let atlas = Atlas::new((size * 8).next_power_of_two().max(256));
+        Font {
+            font,
+            size,
+            shader,
+            quad,
+            atlas,
+            glyphs: HashMap::new(),
+        }
+    }
+
+    // Get a glyph, adding it to the atlas if it's not there yet.
+    fn glyph(&mut self, c: char) -> Glyph {
+        if let Some(glyph) = self.glyphs.get(&c) {
+            return *glyph;
+        }
+        let string = c.to_string();
+        let path: Vec<PathOp> = self
+            .font
+            .render(&string, NO_WRAP, fonterator::TextAlign::Left)
+            .0
+            .collect();
+        let mut glyph = Glyph {
+            ops: path.len(),
+            first: Pt(0.0, 0.0),
+            region: Region::new(0, 0, 0, 0),
+            offset: (0.0, 0.0),
+        };
+        let points = path.iter().flat_map(|op| match *op {
+            PathOp::Move(a) | PathOp::Line(a) => vec![a],
+            PathOp::Quad(a, b) => vec![a, b],
+            PathOp::Cubic(a, b, c) => vec![a, b, c],
+            PathOp::Close() | PathOp::PenWidth(_) => vec![],
+        });
+        let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+        for (i, Pt(x, y)) in points.enumerate() {
+            if i == 0 {
+                glyph.first = Pt(x, y);
+            }
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        // Whitespace and control characters don't need to be drawn.
+        if min.0 < max.0 && min.1 < max.1 {
+            let size = self.size as f32;
+            let pad = PADDING as f32;
+            let width = ((max.0 - min.0) * size).ceil() as u32 + PADDING * 2;
+            let height = ((max.1 - min.1) * size).ceil() as u32 + PADDING * 2;
+            let (mut plotter, stride) = mask(width, height);
+            plotter.set_transform(
+                footile::Transform::with_translate(-min.0, -min.1)
+                    .scale(size, size)
+                    .translate(pad, pad),
+            );
+            plotter.fill(FillRule::NonZero, &path, Matte8::new(255));
+            let coverage = plotter.raster();
+            let mut raster = Raster::<SRgba8>::with_clear(width, height);
+            let rows = coverage.pixels().chunks_exact(stride as usize);
+            for (row, alphas) in raster.rows_mut(()).zip(rows) {
+                for (pixel, alpha) in row.iter_mut().zip(alphas) {
+                    let alpha = u8::from(alpha.alpha());
+                    *pixel = SRgba8::new(255, 255, 255, alpha);
+                }
+            }
+            glyph.region = self.atlas.add(&raster);
+            glyph.offset = (min.0 - pad / size, min.1 - pad / size);
+        }
+        self.glyphs.insert(c, glyph);
+        glyph
+    }
+
+    // Lay out a line of text without wrapping it, getting the byte index,
+    // character and origin (in font heights) of each glyph that's drawn.
+    fn place(&mut self, line: &str) -> Vec<(usize, char, f32, f32)> {
+        let path: Vec<PathOp> = self
+            .font
+            .render(line, NO_WRAP, fonterator::TextAlign::Left)
+            .0
+            .collect();
+        let mut ops = path.iter();
+        let mut placed = Vec::new();
+        for (i, c) in line.char_indices() {
+            let glyph = self.glyph(c);
+            let mut glyph_ops = ops.by_ref().take(glyph.ops);
+            let first = glyph_ops.next();
+            glyph_ops.for_each(drop);
+            if glyph.region.width() == 0 {
+                continue;
+            }
+            if let Some(PathOp::Move(Pt(x, y))) = first {
+                placed.push((i, c, x - glyph.first.0, y - glyph.first.1));
+            }
+        }
+        placed
+    }
+}
+
+/// Builder for [`Text`].
+///
+/// Positions and sizes are in [`Canvas`](super::Canvas) coordinates.  Only
+/// left-to-right scripts are supported.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::{SRgb32, SRgba32}, Canvas, Font, Headless, TextAlign,
+///     TextBuilder,
+/// };
+/// use cala::task::exec;
+/// use cala::video::rgb::SRgba8;
+/// use cala::window::Frame;
+///
+/// std::thread::spawn(|| {
+///     let mut font = Font::normal(32);
+///     let mut text = TextBuilder::new("Hello, world!")
+///         .position(0.0, 0.0)
+///         .size(0.5)
+///         .width(1.0)
+///         .align(TextAlign::Center)
+///         .color(SRgba32::new(1.0, 0.0, 0.0, 1.0))
+///         .finish(&mut font);
+///     // Text with characters longer than a byte can be wrapped anywhere.
+///     let mut wrapped = TextBuilder::new("héllo wörld éééééééééé")
+///         .position(0.0, 0.5)
+///         .size(0.125)
+///         .width(0.25)
+///         .finish(&mut font);
+///     // Text can be drawn with a different font than it was laid out with.
+///     let mut small = Font::normal(8);
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         frame.draw_text(&mut font, &mut text);
+///         frame.draw_text(&mut small, &mut wrapped);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(64, 64);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// let red = SRgba8::new(255, 0, 0, 255);
+/// assert!(gpu.raster().pixels().iter().any(|pixel| *pixel == red));
+/// // The wrapped text takes at least four lines (once the background is
+/// // black, from the second frame).
+/// gpu.run(std::time::Duration::from_millis(16));
+/// let black = SRgba8::new(0, 0, 0, 255);
+/// let mut bottom = (56..64).flat_map(|y| (0..16).map(move |x| (x, y)));
+/// assert!(bottom.any(|(x, y)| gpu.raster().pixel(x, y) != black));
+/// ```
+pub struct TextBuilder<'a> {
+    text: &'a str,
+    position: (f32, f32),
+    size: f32,
+    width: Option<f32>,
+    align: TextAlign,
+    color: SRgba32,
+}
+
+impl<'a> TextBuilder<'a> {
+    /// Create a new `TextBuilder` for white, left-aligned text that isn't
+    /// wrapped.
+    pub fn new(text: &'a str) -> Self {
+        TextBuilder {
+            text,
+            position: (0.0, 0.0),
+            size: 0.05,
+            width: None,
+            align: TextAlign::Left,
+            color: SRgba32::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Set the top-left corner of the text box.  Without a
+    /// [`width()`](TextBuilder::width), this is where lines start
+    /// (left-aligned), are centered on or end (right-aligned).
+    pub fn position(mut self, x: f32, y: f32) -> Self {
+        self.position = (x, y);
+        self
+    }
+
+    /// Set the height of a line.
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Set the width of the text box, wrapping lines that are too long.
+    pub fn width(mut self, width: f32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Set how lines are aligned.
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Set the text color.
+    pub fn color<P: Pixel>(mut self, color: P) -> Self
+    where
+        pix::chan::Ch32: From<<P as Pixel>::Chan>,
+    {
+        self.color = color.convert();
+        self
+    }
+
+    /// Lay out the text, adding any new glyphs to the font's atlas.
+    pub fn finish(self, font: &mut Font) -> Text {
+        let (row, space) = match self.width {
+            Some(width) => (width / self.size, width / self.size),
+            None => (NO_WRAP, 0.0),
+        };
+        let align = match self.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::Right => 1.0,
+        };
+        let mut glyphs = Vec::new();
+        let mut line = 0.0;
+        for paragraph in self.text.split('\n') {
+            // Lines are wrapped here rather than by fonterator, which can
+            // split text in the middle of characters longer than a byte.
+            let placed = font.place(paragraph);
+            let right = |(_, c, x, _): (usize, char, f32, f32)| {
+                let glyph = font.glyphs[&c];
+                x + glyph.offset.0
+                    + glyph.region.width() as f32 / font.size as f32
+            };
+            let mut start = 0;
+            loop {
+                // The first line keeps leading spaces, wrapped lines don't.
+                let left = match start {
+                    0 => 0.0,
+                    _ => placed[start].2,
+                };
+                let mut end = (start + 1).min(placed.len());
+                while end < placed.len() && right(placed[end]) - left <= row {
+                    end += 1;
+                }
+                // Wrap after the last space on the line, if there is one.
+                if end < placed.len() {
+                    let space = paragraph[..placed[end].0]
+                        .rfind(' ')
+                        .filter(|space| *space > placed[start].0);
+                    if let Some(space) = space {
+                        end = start
+                            + placed[start..end]
+                                .iter()
+                                .position(|glyph| glyph.0 > space)
+                                .unwrap_or(end - start);
+                    }
+                }
+                let width = match end {
+                    0 => 0.0,
+                    _ => right(placed[end - 1]) - left,
+                };
+                let shift = (space - width) * align - left;
+                for &(_, c, x, y) in &placed[start..end] {
+                    glyphs.push((c, x + shift, y + line));
+                }
+                line += 1.0;
+                start = end;
+                if start == placed.len() {
+                    break;
+                }
+            }
+        }
+        let mut text = Text {
+            glyphs,
+            position: self.position,
+            size: self.size,
+            color: self.color,
+            group: Group::new(),
+            atlas: font.atlas.texture().0,
+        };
+        text.write(font);
+        text
+    }
+}
+
+/// Text laid out with a [`TextBuilder`], ready to be drawn with
+/// [`Canvas::draw_text()`](super::Canvas::draw_text).
+pub struct Text {
+    // Character and origin of each glyph (in font heights).
+    glyphs: Vec<(char, f32, f32)>,
+    position: (f32, f32),
+    size: f32,
+    color: SRgba32,
+    group: Group,
+    // Texture of the font's atlas that the group was written for, which
+    // changes with the font and when the atlas grows.
+    atlas: Id,
+}
+
+impl Text {
+    // Write the glyphs into the group, adding any that are missing (from a
+    // different font) to the font's atlas first.
+    fn write(&mut self, font: &mut Font) {
+        for (c, _x, _y) in self.glyphs.iter() {
+            font.glyph(*c);
+        }
+        let scale = self.size / font.size as f32;
+        for (id, (c, x, y)) in self.glyphs.iter().enumerate() {
+            let glyph = font.glyphs[c];
+            let transform = Transform::new()
+                .scale(
+                    glyph.region.width() as f32 * scale,
+                    glyph.region.height() as f32 * scale,
+                    1.0,
+                )
+                .translate(
+                    self.position.0 + (x + glyph.offset.0) * self.size,
+                    self.position.1 + (y + glyph.offset.1) * self.size,
+                    0.0,
+                );
+            let coords = font.atlas.coords(glyph.region);
+            self.group
+                .write_tex(id as u32, &font.quad, &transform, coords);
+        }
+        self.atlas = font.atlas.texture().0;
+    }
+
+    // Get ready to draw with a font (re-writing the group if it's not the
+    // font the text was written for, or its atlas has changed since).
+    pub(super) fn prepare<'a>(
+        &mut self,
+        font: &'a mut Font,
+    ) -> (&'a Shader, &'a Texture) {
+        if self.atlas != font.atlas.texture().0 {
+            self.write(font);
+        }
+        (&font.shader, font.atlas.texture())
+    }
+
+    pub(super) fn group(&self) -> &Group {
+        &self.group
+    }
+
+    pub(super) fn color(&self) -> SRgba32 {
+        self.color
+    }
+}
@@ -0,0 +1,295 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    sprite, Group, Ray, Shader, ShaderBuilder, Shape, Texture, Transform,
+};
+use pix::rgb::SRgba8;
+use std::sync::Arc;
+
+// Width and height of a chunk, in tiles.
+const CHUNK: u32 = 16;
+
+// A square of tiles (on every layer) drawn as one group.
+struct Chunk {
+    group: Group,
+    // Whether every slot of the group has been written.
+    written: bool,
+    // Slots of the tiles that changed since the chunk was last written.
+    changed: Vec<u32>,
+    // Number of tiles that aren't empty.
+    filled: usize,
+}
+
+/// A grid of tiles from a tileset [`Texture`], with layers, drawn with
+/// [`Canvas::draw_tilemap()`](super::Canvas::draw_tilemap).
+///
+/// The map is split into chunks of 16×16 tiles, each drawn with its own
+/// [`Group`].  Only tiles that changed since their chunk was last drawn are
+/// sent to the GPU, and only chunks that can be seen through the camera are
+/// drawn.  Layers are drawn in order, so higher layers cover lower ones.
+///
+/// Tile X goes right and Y goes down, and each tile is `tile_size` world
+/// units across (with the top left of the map at the origin).  Tiles in the
+/// tileset are numbered left to right, then top to bottom.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Headless, PixelCamera, Texture, Tilemap,
+/// };
+/// use cala::task::exec;
+/// use cala::video::{rgb::SRgba8, Raster};
+/// use cala::window::Frame;
+/// use std::sync::Arc;
+///
+/// let red = SRgba8::new(255, 0, 0, 255);
+/// let green = SRgba8::new(0, 255, 0, 255);
+/// let (sender, receiver) = std::sync::mpsc::channel();
+/// std::thread::spawn(move || {
+///     // A tileset of two 1×1 pixel tiles.
+///     let mut raster = Raster::<SRgba8>::with_clear(2, 1);
+///     *raster.pixel_mut(0, 0) = red;
+///     *raster.pixel_mut(1, 0) = green;
+///     let tileset = Arc::new(Texture::new(&raster));
+///     // 40×2 tiles that are 4 pixels across, in 3 chunks.
+///     let mut map = Tilemap::new(tileset, (2, 1), (40, 2), 1, 4.0);
+///     for y in 0..2 {
+///         for x in 0..40 {
+///             map.set(0, x, y, Some(0));
+///         }
+///     }
+///     map.set(0, 1, 0, Some(1));
+///     let camera = PixelCamera::new();
+///     let mut frames = 0;
+///     exec!({
+///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+///         sender.send(frame.stats()).unwrap();
+///         // Change a tile in the second frame.
+///         frames += 1;
+///         if frames == 2 {
+///             map.set(0, 0, 1, Some(1));
+///         }
+///         frame.draw_tilemap(&camera, &mut map);
+///     });
+/// });
+///
+/// let mut gpu = Headless::new(8, 8);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(1, 1), red);
+/// assert_eq!(gpu.raster().pixel(6, 1), green);
+/// assert_eq!(gpu.raster().pixel(1, 6), red);
+/// gpu.run(std::time::Duration::from_millis(16));
+/// assert_eq!(gpu.raster().pixel(1, 6), green);
+/// // Only the first chunk can be seen.
+/// let _none = receiver.recv().unwrap();
+/// assert_eq!(receiver.recv().unwrap().draw_calls, 1);
+/// ```
+pub struct Tilemap {
+    shader: Shader,
+    quad: Shape,
+    tileset: Arc<Texture>,
+    // Columns and rows of tiles in the tileset.
+    tiles: (u32, u32),
+    // Width and height of the map in tiles.
+    size: (u32, u32),
+    tile_size: f32,
+    // Tiles of each layer, row by row.
+    layers: Vec<Vec<Option<u32>>>,
+    // Chunks, row by row.
+    chunks: Vec<Chunk>,
+}
+
+impl Tilemap {
+    /// Create a map of empty tiles that's `size` tiles wide and tall, with
+    /// `layers` layers, using a `tileset` that's split into `tiles` columns
+    /// and rows.  Tiles are `tile_size` world units across.
+    pub fn new(
+        tileset: Arc<Texture>,
+        tiles: (u32, u32),
+        size: (u32, u32),
+        layers: usize,
+        tile_size: f32,
+    ) -> Self {
+        assert!(tiles.0 > 0 && tiles.1 > 0);
+        let shader =
+            Shader::new(include!(concat!(env!("OUT_DIR"), "/res/sprite.rs")));
+        let quad = sprite::quad(&shader, SRgba8::new(255, 255, 255, 255));
+        let count = (size.0.div_ceil(CHUNK) * size.1.div_ceil(CHUNK)) as usize;
+        let chunks = (0..count)
+            .map(|_| Chunk {
+                group: Group::new(),
+                written: false,
+                changed: Vec::new(),
+                filled: 0,
+            })
+            .collect();
+        let area = size.0 as usize * size.1 as usize;
+        Tilemap {
+            shader,
+            quad,
+            tileset,
+            tiles,
+            size,
+            tile_size,
+            layers: vec![vec![None; area]; layers],
+            chunks,
+        }
+    }
+
+    /// Get the width and height of the map in tiles.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Get the number of layers.
+    pub fn layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Get the tile at `x`, `y` on a layer (`None` if it's empty).
+    pub fn get(&self, layer: usize, x: u32, y: u32) -> Option<u32> {
+        assert!(x < self.size.0 && y < self.size.1);
+        self.layers[layer][(y * self.size.0 + x) as usize]
+    }
+
+    /// Set the tile at `x`, `y` on a layer, or empty it with `None`.
+    pub fn set(&mut self, layer: usize, x: u32, y: u32, tile: Option<u32>) {
+        assert!(x < self.size.0 && y < self.size.1);
+        let old = std::mem::replace(
+            &mut self.layers[layer][(y * self.size.0 + x) as usize],
+            tile,
+        );
+        if old == tile {
+            return;
+        }
+        let columns = self.size.0.div_ceil(CHUNK);
+        let chunk =
+            &mut self.chunks[(y / CHUNK * columns + x / CHUNK) as usize];
+        match (old, tile) {
+            (None, Some(_)) => chunk.filled += 1,
+            (Some(_), None) => chunk.filled -= 1,
+            _ => {}
+        }
+        if chunk.written {
+            chunk.changed.push(slot(layer, x, y));
+        }
+    }
+
+    /// Get the tileset texture.
+    pub fn tileset(&self) -> &Texture {
+        &self.tileset
+    }
+
+    // Get the range of chunk columns and rows that rays from the corners of
+    // the canvas hit (where they cross Z = 0), or all of them if they don't.
+    fn visible(&self, corners: &[Ray; 4]) -> ((u32, u32), (u32, u32)) {
+        let all = (
+            (0, self.size.0.div_ceil(CHUNK)),
+            (0, self.size.1.div_ceil(CHUNK)),
+        );
+        let (mut min, mut max) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+        for ray in corners.iter() {
+            let distance = if ray.origin[2] == 0.0 {
+                0.0
+            } else {
+                -ray.origin[2] / ray.direction[2]
+            };
+            if !(distance >= 0.0 && distance.is_finite()) {
+                return all;
+            }
+            let point = ray.at(distance);
+            for i in 0..2 {
+                min[i] = min[i].min(point[i]);
+                max[i] = max[i].max(point[i]);
+            }
+        }
+        let span = self.tile_size * CHUNK as f32;
+        let range = |min: f32, max: f32, (_, count): (u32, u32)| {
+            let first = (min / span).floor().clamp(0.0, count as f32) as u32;
+            let last = (max / span).ceil().clamp(0.0, count as f32) as u32;
+            (first, last)
+        };
+        (range(min[0], max[0], all.0), range(min[1], max[1], all.1))
+    }
+
+    // Write the tiles that changed in the chunks that can be seen, and get
+    // the shader, texture and groups to draw them with.
+    pub(super) fn prepare(
+        &mut self,
+        corners: &[Ray; 4],
+    ) -> (&Shader, &Texture, Vec<&Group>) {
+        let ((left, right), (top, bottom)) = self.visible(corners);
+        let columns = self.size.0.div_ceil(CHUNK);
+        let mut visible = Vec::new();
+        for row in top..bottom {
+            for column in left..right {
+                let index = (row * columns + column) as usize;
+                if self.chunks[index].filled == 0 {
+                    continue;
+                }
+                self.write(index, column * CHUNK, row * CHUNK);
+                visible.push(index);
+            }
+        }
+        let chunks = &self.chunks;
+        let groups = visible
+            .into_iter()
+            .map(move |index| &chunks[index].group)
+            .collect();
+        (&self.shader, &self.tileset, groups)
+    }
+
+    // Write the tiles of a chunk (with its top left tile at `x`, `y`) that
+    // changed, or all of them the first time.
+    fn write(&mut self, index: usize, x: u32, y: u32) {
+        let chunk = &mut self.chunks[index];
+        let slots: Vec<u32> = if chunk.written {
+            let mut slots = std::mem::take(&mut chunk.changed);
+            slots.sort_unstable();
+            slots.dedup();
+            slots
+        } else {
+            // Slots have to be written in order the first time.
+            chunk.written = true;
+            (0..self.layers.len() as u32 * CHUNK * CHUNK).collect()
+        };
+        let hidden = Transform::new().scale(0.0, 0.0, 0.0);
+        let (columns, rows) = self.tiles;
+        let size = [1.0 / columns as f32, 1.0 / rows as f32];
+        for s in slots {
+            let layer = (s / (CHUNK * CHUNK)) as usize;
+            let (tx, ty) = (x + s % CHUNK, y + s / CHUNK % CHUNK);
+            let tile = if tx < self.size.0 && ty < self.size.1 {
+                self.layers[layer][(ty * self.size.0 + tx) as usize]
+            } else {
+                None
+            };
+            let group = &mut self.chunks[index].group;
+            match tile {
+                Some(tile) => {
+                    let (column, row) = (tile % columns, tile / columns);
+                    let offset =
+                        [column as f32 * size[0], row as f32 * size[1]];
+                    let ts = self.tile_size;
+                    let transform = Transform::new()
+                        .scale(ts, ts, 1.0)
+                        .translate(tx as f32 * ts, ty as f32 * ts, 0.0);
+                    group.write_tex(s, &self.quad, &transform, (offset, size));
+                }
+                None => group.write(s, &self.quad, &hidden),
+            }
+        }
+    }
+}
+
+// Get the group slot of a tile in its chunk.
+fn slot(layer: usize, x: u32, y: u32) -> u32 {
+    (layer as u32 * CHUNK + y % CHUNK) * CHUNK + x % CHUNK
+}
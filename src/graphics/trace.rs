@@ -0,0 +1,867 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{
+    gl, mat4, post::Pass, registry, Backend, Blend, Cull, Face, GpuCmd, Id,
+    Internal, Pipeline, ShaderBuilder, ShapeBuilder, Transform,
+};
+use crate::window::WindowConfig;
+use pix::{rgb::SRgba8, Raster, Region};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{
+        BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom,
+        Take, Write,
+    },
+    path::Path,
+    time::Duration,
+};
+
+const MAGIC: &[u8; 8] = b"CalaGpu\0";
+const VERSION: u32 = 0;
+const HEADER_LEN: u64 = 12;
+// Longest string, and widest or tallest raster, that's read from a trace (so
+// that broken files can't allocate too much memory).  Rasters also can't be
+// bigger than what's left of the file.
+const MAX_STRING: u32 = 1 << 24;
+const MAX_RASTER: u32 = 1 << 14;
+
+// One frame of commands from a trace.
+struct TraceFrame {
+    elapsed: Duration,
+    aspect: f32,
+    resized: bool,
+    cmds: Vec<GpuCmd>,
+}
+
+// Writes frames to a trace file (on the draw thread).
+pub(super) struct Recorder {
+    file: BufWriter<File>,
+    error: Option<Error>,
+}
+
+/// Start recording all graphics commands to a trace file, starting with the
+/// next frame.  Recording stops when [`stop_recording()`] is called.
+///
+/// Shapes, rasters and shaders are recorded when they are sent to the GPU, so
+/// start recording before creating them to get a trace that can be replayed
+/// with [`Player`].
+pub fn record<P: AsRef<Path>>(path: P) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&VERSION.to_le_bytes())?;
+    let recorder = Recorder { file, error: None };
+    *Internal::new_lazy().recorder.lock().unwrap() = Some(recorder);
+    Ok(())
+}
+
+/// Stop recording graphics commands, returning the first error that happened
+/// while writing the trace (if any).
+pub fn stop_recording() -> Result<()> {
+    let recorder = Internal::new_lazy().recorder.lock().unwrap().take();
+    if let Some(mut recorder) = recorder {
+        if let Some(error) = recorder.error.take() {
+            return Err(error);
+        }
+        recorder.file.flush()?;
+    }
+    Ok(())
+}
+
+// Record a frame, if recording.
+pub(super) fn frame(
+    elapsed: Duration,
+    aspect: f32,
+    resized: bool,
+    cmds: &[GpuCmd],
+) {
+    let mut recorder = Internal::new_lazy().recorder.lock().unwrap();
+    if let Some(ref mut recorder) = *recorder {
+        if recorder.error.is_none() {
+            let f = &mut recorder.file;
+            let result = write_frame(f, elapsed, aspect, resized, cmds);
+            recorder.error = result.err();
+        }
+    }
+}
+
+fn write_frame<W: Write>(
+    w: &mut W,
+    elapsed: Duration,
+    aspect: f32,
+    resized: bool,
+    cmds: &[GpuCmd],
+) -> Result<()> {
+    // Captures can't be replayed, so they aren't recorded.
+    let count = cmds
+        .iter()
+        .filter(|cmd| !matches!(cmd, GpuCmd::Capture(_)))
+        .count();
+    w.write_all(&(elapsed.as_nanos() as u64).to_le_bytes())?;
+    w.write_all(&aspect.to_le_bytes())?;
+    w.write_all(&[resized as u8])?;
+    w.write_all(&(count as u32).to_le_bytes())?;
+    for cmd in cmds {
+        write_cmd(w, cmd)?;
+    }
+    Ok(())
+}
+
+fn write_u32s<W: Write>(w: &mut W, values: &[u32]) -> Result<()> {
+    for value in values {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_ids<W: Write>(w: &mut W, ids: &[Id]) -> Result<()> {
+    for id in ids {
+        write_u32s(w, &[id.index, id.generation])?;
+    }
+    Ok(())
+}
+
+fn write_f32s<W: Write>(w: &mut W, values: &[f32]) -> Result<()> {
+    for value in values {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_transform<W: Write>(w: &mut W, transform: Transform) -> Result<()> {
+    for column in mat4(transform).iter() {
+        write_f32s(w, column)?;
+    }
+    Ok(())
+}
+
+fn write_str<W: Write>(w: &mut W, string: &str) -> Result<()> {
+    write_u32s(w, &[string.len() as u32])?;
+    w.write_all(string.as_bytes())
+}
+
+fn write_raster<W: Write>(w: &mut W, raster: &Raster<SRgba8>) -> Result<()> {
+    write_u32s(w, &[raster.width(), raster.height()])?;
+    w.write_all(raster.as_u8_slice())
+}
+
+fn write_cmd<W: Write>(w: &mut W, cmd: &GpuCmd) -> Result<()> {
+    use GpuCmd::*;
+    match cmd {
+        Background(r, g, b) => {
+            w.write_all(&[0])?;
+            write_f32s(w, &[*r, *g, *b])
+        }
+        Draw(shader, group, pipeline) => {
+            w.write_all(&[1])?;
+            write_ids(w, &[*shader, *group])?;
+            write_pipeline(w, *pipeline)
+        }
+        DrawGraphic(shader, group, raster, pipeline) => {
+            w.write_all(&[2])?;
+            write_ids(w, &[*shader, *group, *raster])?;
+            write_pipeline(w, *pipeline)
+        }
+        SetCamera(camera) => {
+            w.write_all(&[3])?;
+            write_transform(w, *camera)
+        }
+        SetTint(shader, tint) => {
+            w.write_all(&[4])?;
+            write_ids(w, &[*shader])?;
+            write_f32s(w, tint)
+        }
+        RasterId(raster, id) => {
+            w.write_all(&[5])?;
+            write_ids(w, &[*id])?;
+            write_raster(w, raster)
+        }
+        RasterUpdate(id, region, raster) => {
+            w.write_all(&[11])?;
+            write_ids(w, &[*id])?;
+            write_u32s(w, &[region.left() as u32, region.top() as u32])?;
+            write_u32s(w, &[region.width(), region.height()])?;
+            write_raster(w, raster)
+        }
+        ShaderId(builder, id) => {
+            w.write_all(&[6])?;
+            write_ids(w, &[*id])?;
+            w.write_all(&[
+                builder.tint as u8,
+                builder.gradient as u8,
+                builder.graphic as u8,
+                builder.depth as u8,
+                builder.blend as u8,
+            ])?;
+            write_str(w, builder.opengl_frag)?;
+            write_str(w, builder.opengl_vert)
+        }
+        ShapeId(builder, id, shader) => {
+            w.write_all(&[7])?;
+            write_ids(w, &[*id, *shader])?;
+            write_u32s(w, &[builder.faces.len() as u32])?;
+            for face in builder.faces.iter() {
+                if let Some(ref vertices) = face.vertices {
+                    w.write_all(&[1])?;
+                    write_u32s(w, &[vertices.len() as u32])?;
+                    write_f32s(w, vertices)?;
+                } else {
+                    w.write_all(&[0])?;
+                }
+                if let Some(transform) = face.transform {
+                    w.write_all(&[1])?;
+                    write_transform(w, transform)?;
+                } else {
+                    w.write_all(&[0])?;
+                }
+            }
+            Ok(())
+        }
+        GroupId(id) => {
+            w.write_all(&[8])?;
+            write_ids(w, &[*id])
+        }
+        GroupWrite(group, id, shape, transform) => {
+            w.write_all(&[9])?;
+            write_ids(w, &[*group])?;
+            write_u32s(w, &[*id])?;
+            write_ids(w, &[*shape])?;
+            write_transform(w, *transform)
+        }
+        GroupWriteTex(group, id, shape, transform, coords) => {
+            w.write_all(&[10])?;
+            write_ids(w, &[*group])?;
+            write_u32s(w, &[*id])?;
+            write_ids(w, &[*shape])?;
+            write_transform(w, *transform)?;
+            write_f32s(w, &coords.0)?;
+            write_f32s(w, &coords.1)
+        }
+        RasterDrop(id) => {
+            w.write_all(&[12])?;
+            write_ids(w, &[*id])
+        }
+        ShaderDrop(id) => {
+            w.write_all(&[13])?;
+            write_ids(w, &[*id])
+        }
+        ShapeDrop(id) => {
+            w.write_all(&[14])?;
+            write_ids(w, &[*id])
+        }
+        GroupDrop(id) => {
+            w.write_all(&[15])?;
+            write_ids(w, &[*id])
+        }
+        Capture(_) => Ok(()),
+        SetTarget(None) => w.write_all(&[18, 0]),
+        SetTarget(Some((id, width, height, color))) => {
+            w.write_all(&[18, 1])?;
+            write_ids(w, &[*id])?;
+            write_u32s(w, &[*width, *height])?;
+            write_f32s(w, color)
+        }
+        InstanceWrite(group, shape, instances) => {
+            w.write_all(&[19])?;
+            write_ids(w, &[*group, *shape])?;
+            write_u32s(w, &[instances.len() as u32])?;
+            for (id, transform, tint) in instances.iter() {
+                write_u32s(w, &[*id])?;
+                write_transform(w, *transform)?;
+                match tint {
+                    Some(tint) => {
+                        w.write_all(&[1])?;
+                        w.write_all(tint)?;
+                    }
+                    None => w.write_all(&[0])?,
+                }
+            }
+            Ok(())
+        }
+        PostProcess(passes) => {
+            w.write_all(&[20])?;
+            write_u32s(w, &[passes.len() as u32])?;
+            for pass in passes.iter() {
+                match pass {
+                    Pass::Bloom(threshold, intensity, radius) => {
+                        w.write_all(&[0])?;
+                        write_f32s(w, &[*threshold, *intensity, *radius])?;
+                    }
+                    Pass::Vignette(strength) => {
+                        w.write_all(&[1])?;
+                        write_f32s(w, &[*strength])?;
+                    }
+                    Pass::ColorGrade(lut, size) => {
+                        w.write_all(&[2])?;
+                        write_ids(w, &[*lut])?;
+                        write_u32s(w, &[*size])?;
+                    }
+                    Pass::Fxaa => w.write_all(&[3])?,
+                    Pass::Scanlines(strength, spacing) => {
+                        w.write_all(&[4])?;
+                        write_f32s(w, &[*strength])?;
+                        write_u32s(w, &[*spacing])?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_pipeline<W: Write>(
+    w: &mut W,
+    pipeline: Option<Pipeline>,
+) -> Result<()> {
+    match pipeline {
+        None => w.write_all(&[0]),
+        Some(pipeline) => w.write_all(&[
+            1,
+            pipeline.blend as u8,
+            pipeline.depth_test as u8,
+            pipeline.depth_write as u8,
+            pipeline.cull as u8,
+        ]),
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8> {
+    let mut bytes = [0; 1];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_id<R: Read>(r: &mut R) -> Result<Id> {
+    Ok(Id {
+        index: read_u32(r)?,
+        generation: read_u32(r)?,
+    })
+}
+
+fn read_f32<R: Read>(r: &mut R) -> Result<f32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_f32s<R: Read>(r: &mut R, count: usize) -> Result<Vec<f32>> {
+    (0..count).map(|_| read_f32(r)).collect()
+}
+
+fn read_transform<R: Read>(r: &mut R) -> Result<Transform> {
+    let mut mat = [[0.0; 4]; 4];
+    for column in mat.iter_mut() {
+        for value in column.iter_mut() {
+            *value = read_f32(r)?;
+        }
+    }
+    Ok(Transform::from_mat4(mat))
+}
+
+fn read_string<R: Read>(r: &mut R) -> Result<String> {
+    let len = read_u32(r)?;
+    if len > MAX_STRING {
+        return Err(invalid("String in trace is too long"));
+    }
+    let mut bytes = vec![0; len as usize];
+    r.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("String isn't UTF-8"))
+}
+
+// Shader source needs to be `'static`, so it's leaked (once per different
+// source, so replaying in a loop doesn't leak more each time).
+fn read_str<R: Read>(
+    r: &mut R,
+    sources: &mut HashSet<&'static str>,
+) -> Result<&'static str> {
+    let string = read_string(r)?;
+    if let Some(source) = sources.get(string.as_str()) {
+        return Ok(source);
+    }
+    let source = Box::leak(string.into_boxed_str());
+    sources.insert(source);
+    Ok(source)
+}
+
+fn read_raster<R: Read>(r: &mut Take<R>) -> Result<Raster<SRgba8>> {
+    let width = read_u32(r)?;
+    let height = read_u32(r)?;
+    let len = u64::from(width) * u64::from(height) * 4;
+    if width > MAX_RASTER || height > MAX_RASTER || len > r.limit() {
+        return Err(invalid("Raster in trace is too large"));
+    }
+    let mut pixels = vec![0; len as usize];
+    r.read_exact(&mut pixels)?;
+    Ok(Raster::with_u8_buffer(width, height, pixels))
+}
+
+fn read_pipeline<R: Read>(r: &mut R) -> Result<Option<Pipeline>> {
+    if read_u8(r)? == 0 {
+        return Ok(None);
+    }
+    let blend = match read_u8(r)? {
+        0 => Blend::Opaque,
+        1 => Blend::Alpha,
+        2 => Blend::Additive,
+        _ => return Err(invalid("Unknown blend mode in trace")),
+    };
+    let depth_test = read_u8(r)? != 0;
+    let depth_write = read_u8(r)? != 0;
+    let cull = match read_u8(r)? {
+        0 => Cull::None,
+        1 => Cull::Back,
+        2 => Cull::Front,
+        _ => return Err(invalid("Unknown cull mode in trace")),
+    };
+    Ok(Some(Pipeline {
+        blend,
+        depth_test,
+        depth_write,
+        cull,
+    }))
+}
+
+fn read_cmd<R: Read>(
+    r: &mut Take<R>,
+    sources: &mut HashSet<&'static str>,
+) -> Result<GpuCmd> {
+    use GpuCmd::*;
+    Ok(match read_u8(r)? {
+        0 => Background(read_f32(r)?, read_f32(r)?, read_f32(r)?),
+        1 => Draw(read_id(r)?, read_id(r)?, read_pipeline(r)?),
+        2 => DrawGraphic(
+            read_id(r)?,
+            read_id(r)?,
+            read_id(r)?,
+            read_pipeline(r)?,
+        ),
+        3 => SetCamera(read_transform(r)?),
+        4 => {
+            let shader = read_id(r)?;
+            let tint = read_f32s(r, 4)?;
+            SetTint(shader, [tint[0], tint[1], tint[2], tint[3]])
+        }
+        5 => {
+            let id = read_id(r)?;
+            RasterId(read_raster(r)?, id)
+        }
+        11 => {
+            let id = read_id(r)?;
+            let x = read_u32(r)? as i32;
+            let y = read_u32(r)? as i32;
+            let region = Region::new(x, y, read_u32(r)?, read_u32(r)?);
+            RasterUpdate(id, region, read_raster(r)?)
+        }
+        6 => {
+            let id = read_id(r)?;
+            let mut flags = [0; 5];
+            r.read_exact(&mut flags)?;
+            let builder = ShaderBuilder {
+                tint: flags[0] != 0,
+                gradient: flags[1] != 0,
+                graphic: flags[2] != 0,
+                depth: flags[3] != 0,
+                blend: flags[4] != 0,
+                opengl_frag: read_str(r, sources)?,
+                opengl_vert: read_str(r, sources)?,
+            };
+            ShaderId(builder, id)
+        }
+        7 => {
+            let id = read_id(r)?;
+            let shader = read_id(r)?;
+            let count = read_u32(r)?;
+            let mut faces = Vec::new();
+            for _ in 0..count {
+                let vertices = if read_u8(r)? != 0 {
+                    let len = read_u32(r)? as usize;
+                    Some(read_f32s(r, len)?)
+                } else {
+                    None
+                };
+                let transform = if read_u8(r)? != 0 {
+                    Some(read_transform(r)?)
+                } else {
+                    None
+                };
+                faces.push(Face {
+                    vertices,
+                    transform,
+                });
+            }
+            ShapeId(ShapeBuilder { faces }, id, shader)
+        }
+        8 => GroupId(read_id(r)?),
+        9 => GroupWrite(
+            read_id(r)?,
+            read_u32(r)?,
+            read_id(r)?,
+            read_transform(r)?,
+        ),
+        10 => {
+            let group = read_id(r)?;
+            let id = read_u32(r)?;
+            let shape = read_id(r)?;
+            let transform = read_transform(r)?;
+            let coords = read_f32s(r, 4)?;
+            let coords = ([coords[0], coords[1]], [coords[2], coords[3]]);
+            GroupWriteTex(group, id, shape, transform, coords)
+        }
+        12 => RasterDrop(read_id(r)?),
+        13 => ShaderDrop(read_id(r)?),
+        14 => ShapeDrop(read_id(r)?),
+        15 => GroupDrop(read_id(r)?),
+        18 => SetTarget(if read_u8(r)? != 0 {
+            let (id, width, height) = (read_id(r)?, read_u32(r)?, read_u32(r)?);
+            let color = read_f32s(r, 4)?;
+            Some((id, width, height, [color[0], color[1], color[2], color[3]]))
+        } else {
+            None
+        }),
+        19 => {
+            let group = read_id(r)?;
+            let shape = read_id(r)?;
+            let count = read_u32(r)?;
+            let mut instances = Vec::new();
+            for _ in 0..count {
+                let id = read_u32(r)?;
+                let transform = read_transform(r)?;
+                let tint = if read_u8(r)? != 0 {
+                    let mut tint = [0; 4];
+                    r.read_exact(&mut tint)?;
+                    Some(tint)
+                } else {
+                    None
+                };
+                instances.push((id, transform, tint));
+            }
+            InstanceWrite(group, shape, instances)
+        }
+        20 => {
+            let count = read_u32(r)?;
+            let mut passes = Vec::new();
+            for _ in 0..count {
+                passes.push(match read_u8(r)? {
+                    0 => Pass::Bloom(read_f32(r)?, read_f32(r)?, read_f32(r)?),
+                    1 => Pass::Vignette(read_f32(r)?),
+                    2 => Pass::ColorGrade(read_id(r)?, read_u32(r)?),
+                    3 => Pass::Fxaa,
+                    4 => Pass::Scanlines(read_f32(r)?, read_u32(r)?),
+                    _ => return Err(invalid("Unknown effect in trace")),
+                });
+            }
+            PostProcess(passes)
+        }
+        _ => return Err(invalid("Unknown command in trace")),
+    })
+}
+
+/// Player for graphics command traces made with [`record()`].
+///
+/// Traces can be replayed in a window with [`Player::draw_thread()`], on the
+/// CPU with [`Headless::replay()`](super::Headless::replay), or printed as
+/// text with [`Player::dump()`] to diff them.
+///
+/// ```rust
+/// use cala::graphics::{
+///     color::SRgb32, Canvas, Group, Headless, Player, Shader, ShapeBuilder,
+///     Transform,
+/// };
+/// # use cala::graphics::doctest::builder;
+/// use cala::task::exec;
+///
+/// let path = std::env::temp_dir().join("cala-trace-doctest.bin");
+/// cala::graphics::record(&path).unwrap();
+///
+/// std::thread::spawn(|| {
+///     let shader = Shader::new(builder());
+///     let green = [0.0, 1.0, 0.0, 1.0];
+///     let square = ShapeBuilder::quad(&builder(), green).finish(&shader);
+///     let mut group = Group::new();
+///     group.write(0, &square, &Transform::new().scale(0.5, 0.5, 1.0));
+///     exec!({
+///         let mut frame =
+///             cala::window::Frame::new(SRgb32::new(1.0, 1.0, 1.0)).await;
+///         frame.draw(&shader, &group);
+///     });
+/// });
+///
+/// // Render live, while recording.
+/// let mut live = Headless::new(32, 32);
+/// for _ in 0..2 {
+///     live.run(std::time::Duration::from_millis(16));
+/// }
+/// cala::graphics::stop_recording().unwrap();
+///
+/// // Replay the recording.
+/// let mut replayed = Headless::new(32, 32);
+/// let mut player = Player::open(&path).unwrap();
+/// while replayed.replay(&mut player).unwrap() {}
+/// assert_eq!(replayed.raster().as_u8_slice(), live.raster().as_u8_slice());
+///
+/// // Broken traces are errors.
+/// let header = std::fs::read(&path).unwrap()[..12].to_vec();
+/// let mut replay_broken = |cmd: &[u8]| {
+///     let mut trace = header.clone();
+///     trace.extend([0; 13]); // Elapsed time, aspect ratio and resized.
+///     trace.extend([1, 0, 0, 0]); // One command.
+///     trace.extend(cmd);
+///     std::fs::write(&path, trace).unwrap();
+///     let mut player = Player::open(&path).unwrap();
+///     replayed.replay(&mut player).unwrap_err().kind()
+/// };
+/// let invalid = std::io::ErrorKind::InvalidData;
+/// // `Shader::new()`, with an id, settings and 4 GiB of source.
+/// let shader = [&[6][..], &[0; 13], &[255; 4]].concat();
+/// assert_eq!(replay_broken(&shader), invalid);
+/// // `Texture::new()`, with an id and 16384×16384 pixels that aren't there.
+/// let texture = [&[5][..], &[0; 8], &[0, 64, 0, 0, 0, 64, 0, 0]].concat();
+/// assert_eq!(replay_broken(&texture), invalid);
+/// ```
+pub struct Player {
+    // Limited to the rest of the file.
+    file: Take<BufReader<File>>,
+    len: u64,
+    // Shader sources that have been leaked.
+    sources: HashSet<&'static str>,
+    // Whether replaying in a window stopped at an error, and the error (until
+    // it's taken).
+    stopped: bool,
+    error: Option<Error>,
+}
+
+impl Player {
+    /// Open a trace file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut file = BufReader::new(file).take(len);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a graphics trace"));
+        }
+        if read_u32(&mut file)? != VERSION {
+            return Err(invalid("Unsupported graphics trace version"));
+        }
+        Ok(Player {
+            file,
+            len,
+            sources: HashSet::new(),
+            stopped: false,
+            error: None,
+        })
+    }
+
+    // Read the next frame, `None` at the end of the trace.
+    fn frame(&mut self) -> Result<Option<TraceFrame>> {
+        let mut elapsed = [0; 8];
+        match self.file.read_exact(&mut elapsed) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let elapsed = Duration::from_nanos(u64::from_le_bytes(elapsed));
+        let aspect = read_f32(&mut self.file)?;
+        let resized = read_u8(&mut self.file)? != 0;
+        let count = read_u32(&mut self.file)?;
+        let cmds = (0..count)
+            .map(|_| read_cmd(&mut self.file, &mut self.sources))
+            .collect::<Result<_>>()?;
+        Ok(Some(TraceFrame {
+            elapsed,
+            aspect,
+            resized,
+            cmds,
+        }))
+    }
+
+    // Get the commands for the next frame, `None` at the end of the trace.
+    pub(super) fn cmds(&mut self) -> Result<Option<Vec<GpuCmd>>> {
+        Ok(self.frame()?.map(|frame| frame.cmds))
+    }
+
+    /// Replay the trace in a loop, in a window opened with `config`.  Like
+    /// [`draw_thread_with()`](super::draw_thread_with), you should only call
+    /// this on the main thread.
+    ///
+    /// If the trace can't be read, replaying stops and the error is returned
+    /// by [`replay_error()`].
+    pub fn draw_thread(self, config: WindowConfig) {
+        *Internal::new_lazy().player.lock().unwrap() = Some(self);
+        let mut window =
+            window::Window::new(&config.title, replay_runner::<window::Window>);
+        gl::swap_interval(config.vsync);
+        loop {
+            window.run();
+        }
+    }
+
+    /// Write the trace as text, one command per line.
+    pub fn dump<W: Write>(mut self, mut w: W) -> Result<()> {
+        let mut number = 0;
+        while let Some(frame) = self.frame()? {
+            writeln!(
+                w,
+                "Frame {} (elapsed: {:?}, aspect: {}, resized: {})",
+                number, frame.elapsed, frame.aspect, frame.resized
+            )?;
+            for cmd in frame.cmds {
+                writeln!(w, "    {}", describe(&cmd))?;
+            }
+            number += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Take the error that stopped [`Player::draw_thread()`] replaying a trace,
+/// if there was one.
+pub fn replay_error() -> Option<Error> {
+    let mut player = Internal::new_lazy().player.lock().unwrap();
+    player.as_mut().and_then(|player| player.error.take())
+}
+
+// Replay a frame from the trace in `Internal`, restarting at the end (or
+// stopping at an error).
+fn replay_runner<B: Backend>(backend: &mut B, _elapsed: Duration) {
+    let mut player = Internal::new_lazy().player.lock().unwrap();
+    let player = player.as_mut().unwrap();
+    if player.stopped {
+        return;
+    }
+    let cmds = player.cmds().and_then(|cmds| match cmds {
+        Some(cmds) => Ok(cmds),
+        None => {
+            player.file.get_mut().seek(SeekFrom::Start(HEADER_LEN))?;
+            player.file.set_limit(player.len - HEADER_LEN);
+            Ok(player.cmds()?.unwrap_or_default())
+        }
+    });
+    match cmds {
+        Ok(cmds) => {
+            for cmd in cmds {
+                if let Err(error) = backend.execute(cmd) {
+                    registry::report(error);
+                }
+            }
+            backend.end_frame();
+        }
+        Err(error) => {
+            player.stopped = true;
+            player.error = Some(error);
+        }
+    }
+}
+
+// FNV-1a hash, to summarize large data in dumps.
+fn hash(bytes: impl IntoIterator<Item = u8>) -> u32 {
+    bytes.into_iter().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn describe(cmd: &GpuCmd) -> String {
+    use GpuCmd::*;
+    match cmd {
+        Background(r, g, b) => format!("Background({}, {}, {})", r, g, b),
+        Draw(shader, group, pipeline) => {
+            format!("Draw({}, {}, {:?})", shader, group, pipeline)
+        }
+        DrawGraphic(shader, group, raster, pipeline) => format!(
+            "DrawGraphic({}, {}, {}, {:?})",
+            shader, group, raster, pipeline
+        ),
+        SetCamera(camera) => format!("SetCamera({:?})", mat4(*camera)),
+        SetTint(shader, tint) => format!("SetTint({}, {:?})", shader, tint),
+        RasterId(raster, id) => format!(
+            "RasterId({}, {}x{}, hash: {:08X})",
+            id,
+            raster.width(),
+            raster.height(),
+            hash(raster.as_u8_slice().iter().cloned())
+        ),
+        RasterUpdate(id, region, raster) => format!(
+            "RasterUpdate({}, {:?}, hash: {:08X})",
+            id,
+            region,
+            hash(raster.as_u8_slice().iter().cloned())
+        ),
+        ShaderId(builder, id) => format!(
+            "ShaderId({}, tint: {}, gradient: {}, graphic: {}, depth: {}, \
+             blend: {}, hash: {:08X})",
+            id,
+            builder.tint,
+            builder.gradient,
+            builder.graphic,
+            builder.depth,
+            builder.blend,
+            hash(
+                builder
+                    .opengl_frag
+                    .bytes()
+                    .chain(builder.opengl_vert.bytes())
+            )
+        ),
+        ShapeId(builder, id, shader) => {
+            let vertices =
+                builder.faces.iter().filter_map(|f| f.vertices.as_ref());
+            format!(
+                "ShapeId({}, shader: {}, faces: {}, hash: {:08X})",
+                id,
+                shader,
+                builder.faces.len(),
+                hash(vertices.flatten().flat_map(|v| v.to_le_bytes().to_vec()))
+            )
+        }
+        GroupId(id) => format!("GroupId({})", id),
+        GroupWrite(group, id, shape, transform) => format!(
+            "GroupWrite({}, {}, {}, {:?})",
+            group,
+            id,
+            shape,
+            mat4(*transform)
+        ),
+        GroupWriteTex(group, id, shape, transform, coords) => format!(
+            "GroupWriteTex({}, {}, {}, {:?}, {:?})",
+            group,
+            id,
+            shape,
+            mat4(*transform),
+            coords
+        ),
+        RasterDrop(id) => format!("RasterDrop({})", id),
+        ShaderDrop(id) => format!("ShaderDrop({})", id),
+        ShapeDrop(id) => format!("ShapeDrop({})", id),
+        GroupDrop(id) => format!("GroupDrop({})", id),
+        Capture(_) => "Capture".to_string(),
+        SetTarget(target) => format!("SetTarget({:?})", target),
+        InstanceWrite(group, shape, instances) => format!(
+            "InstanceWrite({}, {}, {:?})",
+            group,
+            shape,
+            instances
+                .iter()
+                .map(|(id, transform, tint)| (id, mat4(*transform), tint))
+                .collect::<Vec<_>>()
+        ),
+        PostProcess(passes) => format!("PostProcess({:?})", passes),
+    }
+}
@@ -0,0 +1,534 @@
+// Cala
+// Copyright © 2017-2021 Jeron Aldaron Lau.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT License (https://mit-license.org/)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use super::{headless::mask, Canvas, Texture};
+use footile::{FillRule, PathOp, Pt};
+use pix::{
+    el::Pixel,
+    matte::Matte8,
+    ops::SrcOver,
+    rgb::{Rgba8p, SRgba8},
+    Raster,
+};
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+// Magic number at the start of a zstd stream (which RVG files are).
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+// How a path is painted.
+#[derive(Copy, Clone)]
+enum Paint {
+    Fill(SRgba8),
+    Stroke(f32, SRgba8),
+}
+
+// A path and how it's painted.
+struct Layer {
+    path: Vec<PathOp>,
+    paint: Paint,
+}
+
+/// Builder for a [`Vector`] graphic.
+///
+/// Paths are in
+/// [SVG path data](https://www.w3.org/TR/SVG11/paths.html#PathData) syntax.
+/// Like SVG, paths are drawn up to the first error.
+///
+/// ```rust
+/// use cala::graphics::{color::SRgba8, VectorBuilder};
+///
+/// let vector = VectorBuilder::new(2.0, 2.0)
+///     .fill("M0 0h1v1H0z", SRgba8::new(255, 0, 0, 255))
+///     .finish();
+/// let raster = vector.raster(4, 4);
+/// assert_eq!(raster.pixel(0, 0), SRgba8::new(255, 0, 0, 255));
+/// assert_eq!(raster.pixel(3, 3), SRgba8::new(0, 0, 0, 0));
+///
+/// // A circle, made of two arcs.
+/// let circle = VectorBuilder::new(2.0, 2.0)
+///     .fill("M0 1a1 1 0 0 0 2 0a1 1 0 00-2 0z", SRgba8::new(255, 0, 0, 255))
+///     .finish();
+/// let raster = circle.raster(8, 8);
+/// assert_eq!(raster.pixel(4, 4), SRgba8::new(255, 0, 0, 255));
+/// assert_eq!(raster.pixel(0, 0), SRgba8::new(0, 0, 0, 0));
+/// assert_eq!(raster.pixel(7, 7), SRgba8::new(0, 0, 0, 0));
+///
+/// // The top half, sweeping clockwise.
+/// let half = VectorBuilder::new(2.0, 2.0)
+///     .fill("M0 1A1 1 0 0 1 2 1z", SRgba8::new(255, 0, 0, 255))
+///     .finish();
+/// let raster = half.raster(8, 8);
+/// assert_eq!(raster.pixel(4, 1), SRgba8::new(255, 0, 0, 255));
+/// assert_eq!(raster.pixel(4, 6), SRgba8::new(0, 0, 0, 0));
+/// ```
+pub struct VectorBuilder {
+    width: f32,
+    height: f32,
+    layers: Vec<Layer>,
+}
+
+impl VectorBuilder {
+    /// Create a new `VectorBuilder` for a graphic that's `width` by `height`
+    /// in path coordinates.
+    pub fn new(width: f32, height: f32) -> Self {
+        VectorBuilder {
+            width,
+            height,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Load an RVG file.  Returns `None` if the file isn't a valid RVG, or if
+    /// it uses a join style, fill rule, glyph or pattern, which aren't
+    /// supported.
+    pub fn rvg(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(&ZSTD_MAGIC) {
+            return None;
+        }
+        let graphic = rvg::Graphic::load(data)?;
+        let model = graphic.models.first()?;
+        let mut builder = Self::new(model.width, model.height);
+        for (group, properties) in model.groups.iter() {
+            let mut fill = None;
+            let mut stroke = None;
+            let mut width = 1.0;
+            for property in properties {
+                use rvg::GroupProperty::*;
+                match *property {
+                    FillColorRgba([r, g, b, a]) => {
+                        fill = Some(SRgba8::new(r, g, b, a))
+                    }
+                    StrokeColorRgba([r, g, b, a]) => {
+                        stroke = Some(SRgba8::new(r, g, b, a))
+                    }
+                    StrokeWidth(w) => width = w,
+                    JoinStyle(_) | FillRule(_) | GlyphID(_)
+                    | BitmapPattern(_) | GroupPattern(_) => return None,
+                }
+            }
+            let vertex = |index: u32| {
+                let index = index as usize * 2;
+                Some(Pt(
+                    *graphic.vertex_list.get(index)?,
+                    *graphic.vertex_list.get(index + 1)?,
+                ))
+            };
+            let mut path = Vec::new();
+            for op in graphic.group.get(*group as usize)? {
+                path.push(match *op {
+                    rvg::PathOp::Close() => PathOp::Close(),
+                    rvg::PathOp::Move(a) => PathOp::Move(vertex(a)?),
+                    rvg::PathOp::Line(a) => PathOp::Line(vertex(a)?),
+                    rvg::PathOp::Quad(a, b) => {
+                        PathOp::Quad(vertex(a)?, vertex(b)?)
+                    }
+                    rvg::PathOp::Cubic(a, b, c) => {
+                        PathOp::Cubic(vertex(a)?, vertex(b)?, vertex(c)?)
+                    }
+                });
+            }
+            if let Some(color) = fill {
+                builder.layers.push(Layer {
+                    path: path.clone(),
+                    paint: Paint::Fill(color),
+                });
+            }
+            if let Some(color) = stroke {
+                builder.layers.push(Layer {
+                    path,
+                    paint: Paint::Stroke(width, color),
+                });
+            }
+        }
+        Some(builder)
+    }
+
+    /// Fill a path with a color.
+    pub fn fill<P: Pixel>(mut self, path: &str, color: P) -> Self
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        self.layers.push(Layer {
+            path: parse(path),
+            paint: Paint::Fill(color.convert()),
+        });
+        self
+    }
+
+    /// Stroke a path with a color, `width` wide (in path coordinates).
+    pub fn stroke<P: Pixel>(mut self, path: &str, width: f32, color: P) -> Self
+    where
+        pix::chan::Ch8: From<<P as Pixel>::Chan>,
+    {
+        self.layers.push(Layer {
+            path: parse(path),
+            paint: Paint::Stroke(width, color.convert()),
+        });
+        self
+    }
+
+    /// Finish building the graphic.
+    pub fn finish(self) -> Vector {
+        Vector {
+            width: self.width,
+            height: self.height,
+            layers: self.layers,
+            texture: None,
+        }
+    }
+}
+
+/// A vector graphic, that can be rasterized at any resolution.
+///
+/// Built with a [`VectorBuilder`].
+pub struct Vector {
+    width: f32,
+    height: f32,
+    layers: Vec<Layer>,
+    // Texture, and its size in pixels.
+    texture: Option<(Texture, (u32, u32))>,
+}
+
+impl Vector {
+    /// Return the aspect ratio (`height / width`) of the graphic.
+    pub fn aspect(&self) -> f32 {
+        self.height / self.width
+    }
+
+    /// Rasterize the graphic, stretched to `width` by `height` pixels.
+    pub fn raster(&self, width: u32, height: u32) -> Raster<SRgba8> {
+        let mut raster = Raster::<Rgba8p>::with_clear(width, height);
+        let scale = (width as f32 / self.width, height as f32 / self.height);
+        for layer in self.layers.iter() {
+            // Points are scaled here rather than with a footile transform,
+            // because footile transforms strokes twice.
+            let path = layer.path.iter().map(|op| match *op {
+                PathOp::Move(a) => PathOp::Move(scaled(a, scale)),
+                PathOp::Line(a) => PathOp::Line(scaled(a, scale)),
+                PathOp::Quad(a, b) => {
+                    PathOp::Quad(scaled(a, scale), scaled(b, scale))
+                }
+                PathOp::Cubic(a, b, c) => PathOp::Cubic(
+                    scaled(a, scale),
+                    scaled(b, scale),
+                    scaled(c, scale),
+                ),
+                op => op,
+            });
+            let (mut plotter, _) = mask(width, height);
+            let (matte, color) = match layer.paint {
+                Paint::Fill(color) => (
+                    plotter.fill(FillRule::NonZero, path, Matte8::new(255)),
+                    color,
+                ),
+                Paint::Stroke(width, color) => {
+                    let width = width * (scale.0 * scale.1).sqrt();
+                    let pen = std::iter::once(PathOp::PenWidth(width));
+                    (plotter.stroke(pen.chain(path), Matte8::new(255)), color)
+                }
+            };
+            raster.composite_matte(
+                (),
+                matte,
+                (0, 0, width, height),
+                color.convert(),
+                SrcOver,
+            );
+        }
+        Raster::with_raster(&raster)
+    }
+
+    /// Get a [`Texture`] for drawing the graphic `width` wide (in
+    /// [`Canvas`] coordinates) on a `canvas`.
+    ///
+    /// The graphic is rasterized the first time, and then again whenever its
+    /// size in pixels changes (when the `canvas` is resized), so it stays
+    /// crisp.
+    pub fn texture<C: Canvas>(&mut self, canvas: &C, width: f32) -> &Texture {
+        let pixels = (width * canvas.pixel_width() as f32).ceil().max(1.0);
+        let size = (pixels as u32, (pixels * self.aspect()).ceil() as u32);
+        match self.texture {
+            Some((_, old)) if old == size => {}
+            _ => {
+                let raster = self.raster(size.0, size.1);
+                self.texture = Some((Texture::new(&raster), size));
+            }
+        }
+        &self.texture.as_ref().unwrap().0
+    }
+}
+
+// Parse SVG path data into absolute path operations.
+fn parse(data: &str) -> Vec<PathOp> {
+    let mut path = Vec::new();
+    let _ = Parser { data, index: 0 }.path(&mut path);
+    path
+}
+
+// Parser for SVG path data.
+struct Parser<'a> {
+    data: &'a str,
+    index: usize,
+}
+
+impl Parser<'_> {
+    // Skip whitespace and commas.
+    fn skip(&mut self) {
+        let rest = &self.data[self.index..];
+        let trimmed = rest
+            .trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        self.index += rest.len() - trimmed.len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip();
+        self.data.as_bytes().get(self.index).cloned()
+    }
+
+    // Check if the next token is a number.
+    fn at_number(&mut self) -> bool {
+        matches!(self.peek(), Some(b'0'..=b'9' | b'-' | b'+' | b'.'))
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        let command = self.peek().filter(u8::is_ascii_alphabetic)?;
+        self.index += 1;
+        Some(command)
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip();
+        let bytes = self.data.as_bytes();
+        let start = self.index;
+        let mut end = start;
+        let digits = |mut end: usize| {
+            while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+                end += 1;
+            }
+            end
+        };
+        if let Some(b'-' | b'+') = bytes.get(end) {
+            end += 1;
+        }
+        end = digits(end);
+        if bytes.get(end) == Some(&b'.') {
+            end = digits(end + 1);
+        }
+        if let Some(b'e' | b'E') = bytes.get(end) {
+            let mut exponent = end + 1;
+            if let Some(b'-' | b'+') = bytes.get(exponent) {
+                exponent += 1;
+            }
+            if bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+                end = digits(exponent);
+            }
+        }
+        let number = self.data[start..end].parse().ok()?;
+        self.index = end;
+        Some(number)
+    }
+
+    // Arc flags are a single digit, which may not be followed by a separator.
+    fn flag(&mut self) -> Option<bool> {
+        let flag = match self.peek()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.index += 1;
+        Some(flag)
+    }
+
+    fn point(&mut self, origin: Pt) -> Option<Pt> {
+        Some(Pt(origin.0 + self.number()?, origin.1 + self.number()?))
+    }
+
+    // Parse the whole path, stopping at the first error.
+    fn path(&mut self, path: &mut Vec<PathOp>) -> Option<()> {
+        let mut pen = Pt(0.0, 0.0);
+        let mut start = pen;
+        // Kind of curve and last control point, for `S` and `T`.
+        let mut control = None;
+        let mut command = self.command()?;
+        loop {
+            let origin = if command.is_ascii_lowercase() {
+                pen
+            } else {
+                Pt(0.0, 0.0)
+            };
+            let kind = command.to_ascii_uppercase();
+            let mut next = command;
+            match kind {
+                b'M' => {
+                    pen = self.point(origin)?;
+                    start = pen;
+                    path.push(PathOp::Move(pen));
+                    // Extra points are lines.
+                    next = command - b'M' + b'L';
+                    control = None;
+                }
+                b'L' => {
+                    pen = self.point(origin)?;
+                    path.push(PathOp::Line(pen));
+                    control = None;
+                }
+                b'H' => {
+                    pen = Pt(origin.0 + self.number()?, pen.1);
+                    path.push(PathOp::Line(pen));
+                    control = None;
+                }
+                b'V' => {
+                    pen = Pt(pen.0, origin.1 + self.number()?);
+                    path.push(PathOp::Line(pen));
+                    control = None;
+                }
+                b'C' | b'S' => {
+                    let a = if kind == b'C' {
+                        self.point(origin)?
+                    } else {
+                        smooth(control, b'C', pen)
+                    };
+                    let b = self.point(origin)?;
+                    pen = self.point(origin)?;
+                    path.push(PathOp::Cubic(a, b, pen));
+                    control = Some((b'C', b));
+                }
+                b'Q' | b'T' => {
+                    let a = if kind == b'Q' {
+                        self.point(origin)?
+                    } else {
+                        smooth(control, b'Q', pen)
+                    };
+                    pen = self.point(origin)?;
+                    path.push(PathOp::Quad(a, pen));
+                    control = Some((b'Q', a));
+                }
+                b'Z' => {
+                    pen = start;
+                    path.push(PathOp::Close());
+                    control = None;
+                }
+                b'A' => {
+                    let radii = (self.number()?, self.number()?);
+                    let rotation = self.number()?;
+                    let large = self.flag()?;
+                    let sweep = self.flag()?;
+                    let end = self.point(origin)?;
+                    arc(path, pen, radii, rotation, (large, sweep), end);
+                    pen = end;
+                    control = None;
+                }
+                _ => return None,
+            }
+            if kind == b'Z' || !self.at_number() {
+                command = self.command()?;
+            } else {
+                command = next;
+            }
+        }
+    }
+}
+
+// Scale a point.
+fn scaled(point: Pt, scale: (f32, f32)) -> Pt {
+    Pt(point.0 * scale.0, point.1 * scale.1)
+}
+
+// Add an elliptical arc from `pen` to `end` as cubic curves of up to a quarter
+// turn each, using the conversion in the SVG implementation notes (F.6.5).
+fn arc(
+    path: &mut Vec<PathOp>,
+    pen: Pt,
+    radii: (f32, f32),
+    rotation: f32,
+    (large, sweep): (bool, bool),
+    end: Pt,
+) {
+    if pen == end {
+        return;
+    }
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+        path.push(PathOp::Line(end));
+        return;
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    // Half the chord, in the ellipse's unrotated coordinates.
+    let (dx, dy) = ((pen.0 - end.0) / 2.0, (pen.1 - end.1) / 2.0);
+    let (x, y) = (cos * dx + sin * dy, cos * dy - sin * dx);
+    // Scale up radii that are too small to reach the end.
+    let scale = (x * x) / (rx * rx) + (y * y) / (ry * ry);
+    if scale > 1.0 {
+        rx *= scale.sqrt();
+        ry *= scale.sqrt();
+    }
+    // Center, in the ellipse's unrotated coordinates.
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let (x2, y2) = (x * x, y * y);
+    let mut factor =
+        ((rx2 * ry2 - rx2 * y2 - ry2 * x2) / (rx2 * y2 + ry2 * x2)).max(0.0);
+    factor = factor.sqrt();
+    if large == sweep {
+        factor = -factor;
+    }
+    let (cx, cy) = (factor * rx * y / ry, -factor * ry * x / rx);
+    let center = Pt(
+        cos * cx - sin * cy + (pen.0 + end.0) / 2.0,
+        sin * cx + cos * cy + (pen.1 + end.1) / 2.0,
+    );
+    // Start angle, and how far the arc turns.
+    let start = ((y - cy) / ry).atan2((x - cx) / rx);
+    let mut turn = ((-y - cy) / ry).atan2((-x - cx) / rx) - start;
+    if sweep && turn < 0.0 {
+        turn += TAU;
+    } else if !sweep && turn > 0.0 {
+        turn -= TAU;
+    }
+    // Point on the ellipse, and its tangent, at `angle`.
+    let at = |angle: f32| {
+        let (s, c) = angle.sin_cos();
+        let point = Pt(
+            center.0 + rx * c * cos - ry * s * sin,
+            center.1 + rx * c * sin + ry * s * cos,
+        );
+        let tangent =
+            Pt(-rx * s * cos - ry * c * sin, -rx * s * sin + ry * c * cos);
+        (point, tangent)
+    };
+    let count = (turn.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+    let step = turn / count as f32;
+    // Length of the control arms, relative to the tangent.
+    let arm = 4.0 / 3.0 * (step / 4.0).tan();
+    let (mut from, mut from_tangent) = at(start);
+    for i in 1..=count {
+        let (mut to, to_tangent) = at(start + step * i as f32);
+        if i == count {
+            to = end;
+        }
+        path.push(PathOp::Cubic(
+            Pt(from.0 + arm * from_tangent.0, from.1 + arm * from_tangent.1),
+            Pt(to.0 - arm * to_tangent.0, to.1 - arm * to_tangent.1),
+            to,
+        ));
+        from = to;
+        from_tangent = to_tangent;
+    }
+}
+
+// Get the first control point of a smooth curve, by reflecting the last
+// control point if the previous segment was the same `kind` of curve.
+fn smooth(control: Option<(u8, Pt)>, kind: u8, pen: Pt) -> Pt {
+    match control {
+        Some((k, Pt(x, y))) if k == kind => {
+            Pt(pen.0 * 2.0 - x, pen.1 * 2.0 - y)
+        }
+        _ => pen,
+    }
+}
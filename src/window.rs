@@ -26,7 +26,7 @@ struct FrameFuture;
 pub use window::input::input;
 
 impl Future for FrameFuture {
-    type Output = (std::time::Duration, f32, bool);
+    type Output = (std::time::Duration, f32, bool, u32);
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let internal = Internal::new_lazy();
@@ -40,16 +40,43 @@ impl Future for FrameFuture {
     }
 }
 
+/// A future that returns the rendered output of a [`Frame`].
+///
+/// Returned from [`Frame::capture()`].
+pub struct Capture(Arc<Mutex<CaptureInternal>>);
+
+impl Future for Capture {
+    type Output = Raster<pix::rgb::SRgba8>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut lock = self.0.lock().unwrap();
+        if let Some(raster) = lock.raster.take() {
+            Poll::Ready(raster)
+        } else {
+            lock.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
 /// A Canvas to draw on.
 pub struct Frame {
     // For when drop'd; to notify graphics thread
     pair: Arc<(Mutex<bool>, Condvar)>,
+    // Captures to send to the graphics thread when drop'd
+    captures: Vec<Arc<Mutex<CaptureInternal>>>,
+    // Effects to run over the frame when drop'd
+    effects: Vec<Effect>,
     // Delta time since previous frame
     elapsed: std::time::Duration,
     // Aspect ratio
     aspect: f32,
     // If resized
     resized: bool,
+    // Width in pixels
+    width: u32,
+    // Statistics for the previous frame
+    stats: FrameStats,
 }
 
 impl Frame {
@@ -84,23 +111,231 @@ impl Frame {
         let internal = Internal::new_lazy();
         let mut cmds = internal.cmds.lock().unwrap();
         let pair = internal.pair.clone();
+        let stats = *internal.frame_stats.lock().unwrap();
         if bg_changed {
             cmds.push(GpuCmd::Background(red, green, blue));
         }
         Frame {
             pair,
+            captures: Vec::new(),
+            effects: Vec::new(),
             elapsed: secs.0,
             aspect: secs.1,
             resized: secs.2,
+            width: secs.3,
+            stats,
         }
     }
+
+    /// Get the statistics for the previous frame (all zero for the first).
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Group, Headless, Shader, ShapeBuilder,
+    ///     Texture, Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::video::{rgb::SRgba8, Raster};
+    /// use cala::window::Frame;
+    ///
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// std::thread::spawn(move || {
+    ///     let shader = Shader::new(builder());
+    ///     let square =
+    ///         ShapeBuilder::quad(&builder(), [1.0; 4]).finish(&shader);
+    ///     let mut group = Group::new();
+    ///     group.write(0, &square, &Transform::new());
+    ///     let right = Transform::new().translate(0.5, 0.0, 0.0);
+    ///     group.write(1, &square, &right);
+    ///     let _texture = Texture::new(&Raster::<SRgba8>::with_clear(4, 4));
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///         sender.send(frame.stats()).unwrap();
+    ///         frame.draw(&shader, &group);
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// for _ in 0..3 {
+    ///     gpu.run(std::time::Duration::from_millis(16));
+    /// }
+    /// let _none = receiver.recv().unwrap();
+    /// let first = receiver.recv().unwrap();
+    /// assert_eq!(first.draw_calls, 1);
+    /// assert_eq!(first.vertices, 12);
+    /// assert_eq!(first.texture_uploads, 1);
+    /// let second = receiver.recv().unwrap();
+    /// assert_eq!((second.commands, second.texture_uploads), (1, 0));
+    /// ```
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Capture the output of this frame.  The returned future finishes once
+    /// the graphics thread has drawn everything submitted for this frame,
+    /// after the `Frame` is dropped.
+    ///
+    /// ```rust
+    /// use cala::graphics::{color::SRgb32, Headless};
+    /// use cala::task::exec;
+    /// use cala::video::rgb::SRgba8;
+    /// use cala::window::Frame;
+    ///
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// std::thread::spawn(move || {
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 1.0, 0.0)).await;
+    ///         let capture = frame.capture();
+    ///         drop(frame);
+    ///         sender.send(capture.await).unwrap();
+    ///     })
+    /// });
+    ///
+    /// let mut gpu = Headless::new(32, 32);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// let _first = receiver.recv().unwrap();
+    /// let second = receiver.recv().unwrap();
+    /// assert_eq!(second.pixel(16, 16), SRgba8::new(0, 255, 0, 255));
+    /// ```
+    pub fn capture(&mut self) -> Capture {
+        let capture = Arc::new(Mutex::new(CaptureInternal {
+            waker: None,
+            raster: None,
+        }));
+        self.captures.push(capture.clone());
+        Capture(capture)
+    }
+
+    /// Draw on a [`RenderTarget`] instead of the screen, after clearing it to
+    /// `color`.  Drawing goes back to the screen when the returned
+    /// [`Offscreen`] canvas is dropped, and the target's texture can then be
+    /// drawn like any other.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Group, Headless, RenderTarget, Shader,
+    ///     ShaderBuilder, ShapeBuilder, Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::video::rgb::{SRgb8, SRgba8};
+    /// use cala::window::Frame;
+    ///
+    /// std::thread::spawn(|| {
+    ///     let graphic = || ShaderBuilder {
+    ///         gradient: false,
+    ///         graphic: true,
+    ///         ..builder()
+    ///     };
+    ///     let colors = Shader::new(builder());
+    ///     let textured = Shader::new(graphic());
+    ///     let white = ShapeBuilder::quad(&builder(), [1.0; 4]);
+    ///     let white = white.finish(&colors);
+    ///     let quad = ShapeBuilder::quad(&graphic(), [1.0; 4]);
+    ///     let quad = quad.finish(&textured);
+    ///     let mut corner = Group::new();
+    ///     corner.write(0, &white, &Transform::new().scale(0.5, 0.5, 1.0));
+    ///     let mut screen = Group::new();
+    ///     screen.write(0, &quad, &Transform::new());
+    ///     let target = RenderTarget::new(2, 2);
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(0.0, 0.0, 0.0)).await;
+    ///         let mut offscreen =
+    ///             frame.render_to(&target, SRgb8::new(0, 255, 0));
+    ///         offscreen.draw(&colors, &corner);
+    ///         drop(offscreen);
+    ///         frame.draw_graphic(&textured, &screen, target.texture());
+    ///     });
+    /// });
+    ///
+    /// let mut gpu = Headless::new(8, 8);
+    /// gpu.run(std::time::Duration::from_millis(16));
+    /// assert_eq!(gpu.raster().pixel(1, 1), SRgba8::new(255, 255, 255, 255));
+    /// assert_eq!(gpu.raster().pixel(6, 1), SRgba8::new(0, 255, 0, 255));
+    /// assert_eq!(gpu.raster().pixel(1, 6), SRgba8::new(0, 255, 0, 255));
+    /// ```
+    pub fn render_to<'a, P: pix::el::Pixel>(
+        &'a mut self,
+        target: &'a RenderTarget,
+        color: P,
+    ) -> Offscreen<'a>
+    where
+        pix::chan::Ch32: From<<P as pix::el::Pixel>::Chan>,
+    {
+        Offscreen::new(target, color, self.elapsed)
+    }
+
+    /// Run full-screen `effects` in order over everything drawn in this
+    /// frame, once the `Frame` is dropped (before it's captured or shown).
+    /// Replaces the effects set before.
+    ///
+    /// ```rust
+    /// use cala::graphics::{
+    ///     color::SRgb32, Canvas, Effect, Group, Headless, Lut, Shader,
+    ///     ShapeBuilder, Transform,
+    /// };
+    /// # use cala::graphics::doctest::builder;
+    /// use cala::task::exec;
+    /// use cala::video::rgb::SRgba8;
+    /// use cala::window::Frame;
+    /// use std::sync::Arc;
+    ///
+    /// std::thread::spawn(|| {
+    ///     let shader = Shader::new(builder());
+    ///     let white = [1.0; 4];
+    ///     let square = ShapeBuilder::quad(&builder(), white).finish(&shader);
+    ///     let mut group = Group::new();
+    ///     let middle = Transform::new().scale(0.5, 0.5, 1.0);
+    ///     group.write(0, &square, &middle.translate(0.25, 0.25, 0.0));
+    ///     // Darken every other row, then invert the colors.
+    ///     let invert = Lut::from_fn(2, |rgb| rgb.map(|c| 1.0 - c));
+    ///     let effects = [
+    ///         Effect::Scanlines {
+    ///             strength: 1.0,
+    ///             spacing: 1,
+    ///         },
+    ///         Effect::ColorGrade(Arc::new(invert)),
+    ///     ];
+    ///     exec!({
+    ///         let mut frame = Frame::new(SRgb32::new(1.0, 0.0, 0.0)).await;
+    ///         frame.draw(&shader, &group);
+    ///         frame.post_process(&effects);
+    ///     });
+    /// });
+    ///
+    /// let black = SRgba8::new(0, 0, 0, 255);
+    /// let white = SRgba8::new(255, 255, 255, 255);
+    /// let mut gpu = Headless::new(8, 8);
+    /// for _ in 0..2 {
+    ///     gpu.run(std::time::Duration::from_millis(16));
+    /// }
+    /// assert_eq!(gpu.raster().pixel(0, 0), SRgba8::new(0, 255, 255, 255));
+    /// assert_eq!(gpu.raster().pixel(4, 4), black);
+    /// assert_eq!(gpu.raster().pixel(4, 5), white);
+    /// ```
+    pub fn post_process(&mut self, effects: &[Effect]) {
+        self.effects = effects.to_vec();
+    }
 }
 
 impl Canvas for Frame {
     fn draw(&mut self, shader: &Shader, group: &Group) {
         let internal = Internal::new_lazy();
         let mut cmds = internal.cmds.lock().unwrap();
-        cmds.push(GpuCmd::Draw(shader.0, group.0));
+        cmds.push(GpuCmd::Draw(shader.0, group.0, None));
+    }
+
+    fn draw_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        pipeline: Pipeline,
+    ) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::Draw(shader.0, group.0, Some(pipeline)));
     }
 
     fn set_camera(&mut self, camera: Transform) {
@@ -131,7 +366,24 @@ impl Canvas for Frame {
     ) {
         let internal = Internal::new_lazy();
         let mut cmds = internal.cmds.lock().unwrap();
-        cmds.push(GpuCmd::DrawGraphic(shader.0, group.0, graphic.0));
+        cmds.push(GpuCmd::DrawGraphic(shader.0, group.0, graphic.0, None));
+    }
+
+    fn draw_graphic_with(
+        &mut self,
+        shader: &Shader,
+        group: &Group,
+        graphic: &Texture,
+        pipeline: Pipeline,
+    ) {
+        let internal = Internal::new_lazy();
+        let mut cmds = internal.cmds.lock().unwrap();
+        cmds.push(GpuCmd::DrawGraphic(
+            shader.0,
+            group.0,
+            graphic.0,
+            Some(pipeline),
+        ));
     }
 
     fn elapsed(&self) -> std::time::Duration {
@@ -145,10 +397,27 @@ impl Canvas for Frame {
     fn resized(&self) -> bool {
         self.resized
     }
+
+    fn pixel_width(&self) -> u32 {
+        self.width
+    }
 }
 
 impl Drop for Frame {
     fn drop(&mut self) {
+        if !self.effects.is_empty() || !self.captures.is_empty() {
+            let internal = Internal::new_lazy();
+            let mut cmds = internal.cmds.lock().unwrap();
+            // The effects' lookup tables are dropped after this, so they're
+            // still on the GPU when the effects run.
+            if !self.effects.is_empty() {
+                let passes = self.effects.iter().map(Effect::pass).collect();
+                cmds.push(GpuCmd::PostProcess(passes));
+            }
+            for capture in self.captures.drain(..) {
+                cmds.push(GpuCmd::Capture(capture));
+            }
+        }
         let (lock, cvar) = &*self.pair;
         let mut started = lock.lock().unwrap();
         *started = true;
@@ -156,3 +425,49 @@ impl Drop for Frame {
         cvar.notify_one();
     }
 }
+
+/// Settings for the window opened by
+/// [`draw_thread_with()`](crate::graphics::draw_thread_with).
+///
+/// ```rust,no_run
+/// use cala::window::WindowConfig;
+///
+/// cala::graphics::draw_thread_with(
+///     WindowConfig::new()
+///         .title("My Game")
+///         .vsync(false),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct WindowConfig {
+    pub(crate) title: String,
+    pub(crate) vsync: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig::new()
+    }
+}
+
+impl WindowConfig {
+    /// Create a new `WindowConfig` for a window with vsync.
+    pub fn new() -> Self {
+        WindowConfig {
+            title: env!("CARGO_PKG_NAME").to_string(),
+            vsync: true,
+        }
+    }
+
+    /// Set the window title.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Set whether or not to wait for vertical sync before showing a frame.
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+}